@@ -4,13 +4,17 @@ use poise::serenity_prelude as serenity;
 use tracing::{error, info};
 
 use shaggy::chat::handler::on_event;
-use shaggy::music::commands::music;
-use shaggy::recipe::commands::recipe;
+use shaggy::music::commands::{music, playlist};
+use shaggy::music::library::scan_library;
+use shaggy::recipe::commands::{recipe, shopping};
 use shaggy::shared::commands::{embed, help, warn, warnings};
-use shaggy::shared::scheduler::{setup_daily_recipe_scheduler, setup_reminder_scheduler};
+use shaggy::shared::scheduler::{
+    setup_daily_recipe_scheduler, setup_daily_song_scheduler, setup_reminder_scheduler,
+    setup_weekly_music_stats_scheduler,
+};
 use shaggy::shared::types::{Data, Error};
 use shaggy::shared::db::init_db;
-use shaggy::voice::commands::tts;
+use shaggy::voice::commands::{tts, voice};
 use shaggy::reminder::commands::remind;
 use songbird::SerenityInit;
 
@@ -34,11 +38,50 @@ async fn main() -> Result<(), Error> {
     let schedule_str = env::var("DAILY_RECIPE_SCHEDULE")
         .expect("Expected DAILY_RECIPE_SCHEDULE in the environment");
 
+    // The song of the day is optional: it only runs when a channel is configured
+    let song_channel = env::var("DAILY_SONG_CHANNEL_ID")
+        .ok()
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| {
+            serenity::ChannelId::new(
+                id.trim().parse::<u64>().expect("DAILY_SONG_CHANNEL_ID must be a valid number"),
+            )
+        });
+    let song_schedule_str = env::var("DAILY_SONG_SCHEDULE")
+        .ok()
+        .filter(|schedule| !schedule.trim().is_empty())
+        .unwrap_or_else(|| schedule_str.clone());
+
+    // The weekly music summary is optional as well
+    let stats_channel = env::var("MUSIC_STATS_CHANNEL_ID")
+        .ok()
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| {
+            serenity::ChannelId::new(
+                id.trim().parse::<u64>().expect("MUSIC_STATS_CHANNEL_ID must be a valid number"),
+            )
+        });
+    let stats_schedule_str = env::var("MUSIC_STATS_SCHEDULE")
+        .ok()
+        .filter(|schedule| !schedule.trim().is_empty())
+        .unwrap_or_else(|| "0 0 18 * * Sun".to_string());
+
     init_db()?;
 
+    tokio::spawn(async {
+        match scan_library().await {
+            Ok(Some(summary)) => info!(
+                "Music library indexed: {} tracks ({} added, {} updated, {} removed), {} playlists",
+                summary.total, summary.added, summary.updated, summary.removed, summary.playlists
+            ),
+            Ok(None) => {}
+            Err(e) => error!("Music library scan failed: {}", e),
+        }
+    });
+
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![help(), embed(), recipe(), music(), tts(), remind(), warn(), warnings()],
+            commands: vec![help(), embed(), recipe(), shopping(), music(), playlist(), tts(), voice(), remind(), warn(), warnings()],
             event_handler: |ctx, event, framework, data| {
                 Box::pin(async move {
                     if let Err(e) = on_event(ctx, event, framework, data).await {
@@ -51,6 +94,8 @@ async fn main() -> Result<(), Error> {
         })
         .setup(move |ctx, _ready, framework| {
             let schedule = schedule_str.clone();
+            let song_schedule = song_schedule_str.clone();
+            let stats_schedule = stats_schedule_str.clone();
             Box::pin(async move {
                 info!("Registering commands globally...");
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
@@ -58,8 +103,14 @@ async fn main() -> Result<(), Error> {
 
                 setup_daily_recipe_scheduler(ctx, &schedule, recipe_channel).await?;
                 setup_reminder_scheduler(ctx, reminder_channel).await?;
+                if let Some(song_channel) = song_channel {
+                    setup_daily_song_scheduler(ctx, &song_schedule, song_channel).await?;
+                }
+                if let Some(stats_channel) = stats_channel {
+                    setup_weekly_music_stats_scheduler(ctx, &stats_schedule, stats_channel).await?;
+                }
 
-                Ok(Data {})
+                Ok(Data::default())
             })
         })
         .build();
@@ -1,18 +1,58 @@
-use std::env;
 use std::path::Path;
+use poise::ChoiceParameter;
+use rand::seq::SliceRandom;
 use tokio::fs;
 use anyhow::Context as _;
 use poise::serenity_prelude as serenity;
 use poise::serenity_prelude::{CreateAttachment, CreateEmbed};
-use poise::ChoiceParameter;
-use crate::music::models::{FinalFantasyExpansion, Game, KingdomHeartsTitle};
-use crate::music::utils::get_random_song;
-use crate::shared::types::{Context, Error};
+use songbird::Call;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::music::models::{QueuedSong, QuizGuess, QuizMedium, SelectionMode, SongAction, StatsPeriod};
+use crate::music::quiz::{distinct_answers, run_quiz, voice_quiz_running, QuizSettings, QuizSlot};
+use crate::music::radio::{run_radio, RadioStation};
+use crate::music::library::{catalog_folder, list_entries, list_series, scan_library, track_path};
+use crate::music::utils::{
+    enqueue_song, format_position, get_random_song, is_dj, now_playing_embed, play_song_now, search_tracks, select_song,
+    song_components, stats_embed, track_embed, transcode_to_fit, upload_limit, votes_needed, with_cover_art,
+    with_cover_art_within, FAVORITE_BUTTON_PREFIX, PLAY_BUTTON_PREFIX, RATING_MENU_PREFIX,
+};
+use crate::shared::db::{
+    add_playlist_track, create_playlist, delete_playlist_owned, find_playlist, get_music_favorites, get_music_settings,
+    get_music_stats, get_music_track, get_music_tracks_in_folder, get_playlist_tracks, get_quiz_leaderboard,
+    get_top_rated_tracks, get_visible_playlists, log_music_play, remove_playlist_track, set_music_dj_role,
+    set_music_prefer_rated, set_music_radio_idle, set_music_rating, set_music_selection, set_music_skip_ratio,
+    toggle_music_favorite, MusicTrackRow, PlaylistRow,
+};
+use crate::shared::types::{Context, Data, Error};
+use crate::voice::utils::{cleanup_file, find_user_voice_channel, guild_call, join_voice_channel, voice_channel_listeners};
 
 /// Play music from the collection.
-#[poise::command(slash_command, subcommands("music_random", "music_ff", "music_kh"))]
+#[poise::command(
+    slash_command,
+    subcommands(
+        "music_random",
+        "music_browse",
+        "music_search",
+        "music_play",
+        "music_queue",
+        "music_radio",
+        "music_skip",
+        "music_pause",
+        "music_resume",
+        "music_stop",
+        "music_nowplaying",
+        "music_favorites",
+        "music_top",
+        "music_stats",
+        "music_quiz",
+        "music_rescan",
+        "music_settings"
+    )
+)]
 pub async fn music(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say("Use `/music random`, `/music ff`, or `/music kh`.").await?;
+    ctx.say("Use `/music random`, `/music browse`, `/music search`, `/music play`, or `/music queue add`.").await?;
     Ok(())
 }
 
@@ -21,90 +61,383 @@ pub async fn music(ctx: Context<'_>) -> Result<(), Error> {
 pub async fn music_random(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let music_folder = env::var("MUSIC_FOLDER").expect("Expected MUSIC_FOLDER in the environment");
-    match get_random_song(Path::new(&music_folder)).await {
-        Ok(Some(song_path)) => {
-            let file_name = song_path
-                .file_name()
-                .map(|name| name.to_string_lossy().into_owned())
-                .unwrap_or_else(|| "unknown_song.mp3".to_string());
-
-            let file_content = fs::read(&song_path)
-                .await
-                .with_context(|| format!("Failed to read song file: {}", song_path.display()))?;
+    match get_random_song("", ctx.guild_id()) {
+        Ok(Some(track)) => {
+            send_random_song_file(ctx, "", track).await?;
+        }
+        Ok(None) => {
+            ctx.say("Couldn't find any songs in the music library.")
+                .await?;
+        }
+        Err(e) => {
+            tracing::error!("Error getting random song: {:?}", e);
+            ctx.say("An error occurred while trying to get a random song.")
+                .await?;
+        }
+    }
+    Ok(())
+}
 
-            let attachment = CreateAttachment::bytes(file_content, &file_name);
+/// Get a random song from a series or one of its entries, e.g. Final Fantasy › XIV › Endwalker.
+#[poise::command(slash_command, rename = "browse")]
+pub async fn music_browse(
+    ctx: Context<'_>,
+    #[description = "Series folder to pick music from"]
+    #[autocomplete = "autocomplete_series"]
+    series: String,
+    #[description = "Entry folder within the series (default: the whole series)"]
+    #[autocomplete = "autocomplete_entry"]
+    entry: Option<String>,
+) -> Result<(), Error> {
+    let music_folder = match catalog_folder(Some(&series), entry.as_deref()) {
+        Ok(folder) => folder,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
 
-            let embed = CreateEmbed::new()
-                .title("🎶 Random Song")
-                .description(format!("Playing: **{}**", file_name))
-                .color(serenity::Colour::PURPLE);
+    ctx.defer().await?;
 
-            ctx.send(
-                poise::CreateReply::default()
-                    .embed(embed)
-                    .attachment(attachment),
-            )
-                .await?;
+    match get_random_song(&music_folder, ctx.guild_id()) {
+        Ok(Some(track)) => {
+            send_random_song_file(ctx, &music_folder, track).await?;
         }
         Ok(None) => {
             ctx.say(format!(
-                "Couldn't find any MP3 songs in the `{}` folder.",
+                "Couldn't find any songs in the `{}` folder.",
                 music_folder
             ))
                 .await?;
         }
         Err(e) => {
-            tracing::error!("Error getting random song: {:?}", e);
-            ctx.say("An error occurred while trying to get a random song.")
+            tracing::error!("Error getting random song: {}", e);
+            ctx.say("An error occurred while trying to find a song.")
                 .await?;
         }
     }
     Ok(())
 }
 
-/// Get a random song from the Final Fantasy folder.
-#[poise::command(slash_command, rename = "ff")]
-pub async fn music_ff(
+async fn autocomplete_series(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    list_series()
+        .await
+        .into_iter()
+        .filter(|s| s.to_lowercase().contains(&partial))
+        .take(25)
+        .collect()
+}
+
+async fn autocomplete_entry(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    // The series picked in the same command decides which entries are offered.
+    let series = match ctx {
+        poise::Context::Application(app) => app.args.iter().find_map(|arg| match (arg.name, &arg.value) {
+            ("series", serenity::ResolvedValue::String(s)) => Some(s.to_string()),
+            _ => None,
+        }),
+        _ => None,
+    };
+    let Some(series) = series else { return Vec::new(); };
+
+    let partial = partial.to_lowercase();
+    list_entries(&series)
+        .await
+        .into_iter()
+        .filter(|e| e.to_lowercase().contains(&partial))
+        .take(25)
+        .map(|e| serenity::AutocompleteChoice::new(e.replace('/', " › "), e))
+        .collect()
+}
+
+/// Find a song by name and send it, play it or queue it.
+#[poise::command(slash_command, rename = "search")]
+pub async fn music_search(
     ctx: Context<'_>,
-    #[description = "Which expansion to pick music from"] expansion: FinalFantasyExpansion,
+    #[description = "Song title, artist, album or file name"]
+    #[autocomplete = "autocomplete_track"]
+    query: String,
+    #[description = "What to do with the song (default: send the file)"] action: Option<SongAction>,
 ) -> Result<(), Error> {
-    let base_folder = Game::FF.folder_name();
-    let expansion_folder = expansion.folder_name();
+    ctx.defer().await?;
 
-    let base_path = env::var("MUSIC_FOLDER").expect("Expected MUSIC_FOLDER in the environment");
-    let music_dir = Path::new(&base_path).join(base_folder).join(expansion_folder);
+    let track = match resolve_track(&query) {
+        Ok(Some(track)) => track,
+        Ok(None) => {
+            ctx.say(format!("Couldn't find any songs matching '{}'.", query.trim())).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::error!("Error searching songs: {}", e);
+            ctx.say("An error occurred while searching for songs.").await?;
+            return Ok(());
+        }
+    };
 
-    match get_random_song(&music_dir).await {
-        Ok(Some(song_path)) => {
-            let file_name = song_path
-                .file_name()
-                .map(|name| name.to_string_lossy().into_owned())
-                .unwrap_or_else(|| "unknown_song.mp3".to_string());
+    match action.unwrap_or(SongAction::Attach) {
+        SongAction::Attach => {
+            if !send_song_file(ctx, &track, "🔎 Found Song").await? {
+                ctx.say(format!(
+                    "**{}** is larger than this server's upload limit and couldn't be transcoded (is ffmpeg installed?). \
+                     Try playing it in voice instead.",
+                    track.title
+                ))
+                    .await?;
+            }
+        }
+        SongAction::Play => {
+            let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+            play_track_now(ctx, &call_lock, track).await?;
+        }
+        SongAction::Queue => {
+            let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+            let title = track.title.clone();
+            let song = queued_song(ctx, track);
+            let position = enqueue_song(&call_lock, song, ctx.serenity_context().http.clone(), ctx.channel_id()).await;
+            if position == 0 {
+                ctx.say(format!("▶️ Playing **{}** now.", title)).await?;
+            } else {
+                ctx.say(format!("Added **{}** to the queue at position {}.", title, position)).await?;
+            }
+        }
+    }
+    Ok(())
+}
 
-            let file_content = fs::read(&song_path)
-                .await
-                .with_context(|| format!("Failed to read song file: {}", song_path.display()))?;
+/// Resolves a song option: autocomplete choices carry the track id, anything else typed by hand
+/// is searched for.
+fn resolve_track(query: &str) -> Result<Option<MusicTrackRow>, Error> {
+    match query.trim().strip_prefix("id:").and_then(|id| id.parse::<i64>().ok()) {
+        Some(id) => get_music_track(id),
+        None => search_tracks(query, 1).map(|tracks| tracks.into_iter().next()),
+    }
+}
 
-            let attachment = CreateAttachment::bytes(file_content, &file_name);
+async fn autocomplete_track(_ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    if partial.trim().is_empty() {
+        return Vec::new();
+    }
+    match search_tracks(partial, 25) {
+        Ok(tracks) => tracks
+            .into_iter()
+            .map(|track| {
+                let context = track.artist.as_deref().unwrap_or(&track.series);
+                let mut label = if context.is_empty() { track.title.clone() } else { format!("{} — {}", track.title, context) };
+                // Discord rejects choice names longer than 100 characters.
+                if label.chars().count() > 100 {
+                    label = label.chars().take(99).collect::<String>() + "…";
+                }
+                serenity::AutocompleteChoice::new(label, format!("id:{}", track.id))
+            })
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Song autocomplete failed: {}", e);
+            Vec::new()
+        }
+    }
+}
 
-            let embed = CreateEmbed::new()
-                .title("🎶 Random Song")
-                .description(format!("Playing: **{}**", file_name))
-                .color(serenity::Colour::PURPLE);
+/// Replies with a track as an attachment, described by its tags. Songs over the server's
+/// upload limit are transcoded to Opus first; returns `false` without replying if that isn't
+/// possible.
+async fn send_song_file(ctx: Context<'_>, track: &MusicTrackRow, heading: &str) -> Result<bool, Error> {
+    let song_path = track_path(track);
+    let mut file_name = song_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "unknown_song.mp3".to_string());
 
-            ctx.send(
-                poise::CreateReply::default()
-                    .embed(embed)
-                    .attachment(attachment),
-            )
-                .await?;
+    let limit = upload_limit(ctx.guild().map(|g| g.premium_tier));
+    let mut embed = track_embed(track, heading);
+
+    let file_content = if (track.size as u64) <= limit {
+        fs::read(&song_path)
+            .await
+            .with_context(|| format!("Failed to read song file: {}", song_path.display()))?
+    } else {
+        match transcode_to_fit(&song_path, track.duration_secs, limit).await {
+            Ok(Some(transcoded)) => {
+                let content = fs::read(&transcoded).await;
+                cleanup_file(transcoded).await;
+                file_name = format!("{}.ogg", Path::new(&file_name).file_stem().unwrap_or_default().to_string_lossy());
+                embed = embed.field("Note", "Transcoded to Opus to fit the upload limit.", false);
+                content.context("Failed to read transcoded song")?
+            }
+            Ok(None) => return Ok(false),
+            Err(e) => {
+                tracing::warn!("Failed to transcode {}: {}", song_path.display(), e);
+                return Ok(false);
+            }
+        }
+    };
+
+    let budget = limit.saturating_sub(file_content.len() as u64);
+    let attachment = CreateAttachment::bytes(file_content, &file_name);
+    let (embed, cover) = with_cover_art_within(embed, &song_path, budget).await;
+
+    let mut reply = poise::CreateReply::default()
+        .embed(embed)
+        .attachment(attachment)
+        .components(song_components(track.id, ctx.guild_id().is_some()));
+    if let Some(cover) = cover {
+        reply = reply.attachment(cover);
+    }
+    ctx.send(reply).await?;
+    log_play(ctx, track);
+    Ok(true)
+}
+
+/// Sends a randomly picked song, rerolling to a song from the same folder that fits the upload
+/// limit when the pick is too big and can't be transcoded.
+async fn send_random_song_file(ctx: Context<'_>, music_folder: &str, track: MusicTrackRow) -> Result<(), Error> {
+    if send_song_file(ctx, &track, "🎶 Random Song").await? {
+        return Ok(());
+    }
+
+    let limit = upload_limit(ctx.guild().map(|g| g.premium_tier));
+    let guild_id = ctx.guild_id().map(|g| g.get() as i64);
+    if let Some(smaller) = select_song(music_folder, Some(limit as i64), guild_id)?
+        && send_song_file(ctx, &smaller, "🎶 Random Song").await?
+    {
+        return Ok(());
+    }
+
+    ctx.say(format!(
+        "**{}** is larger than this server's {} MB upload limit and couldn't be transcoded (is ffmpeg installed?), \
+         and there are no smaller songs to pick instead. Try `/music play` to listen in voice.",
+        track.title,
+        limit / (1024 * 1024)
+    ))
+        .await?;
+    Ok(())
+}
+
+fn queued_song(ctx: Context<'_>, track: MusicTrackRow) -> QueuedSong {
+    QueuedSong::new(track, ctx.guild_id().unwrap_or_default(), ctx.author().id, &ctx.command().qualified_name)
+}
+
+/// Records a song sent as a file in the guild's play history. Voice plays are logged by the
+/// track itself once they start.
+fn log_play(ctx: Context<'_>, track: &MusicTrackRow) {
+    if let Some(guild_id) = ctx.guild_id()
+        && let Err(e) = log_music_play(
+            guild_id.get() as i64,
+            track.id,
+            Some(ctx.author().id.get() as i64),
+            &ctx.command().qualified_name,
+        )
+    {
+        tracing::warn!("Failed to log music play: {}", e);
+    }
+}
+
+/// Starts a track in the call right away and replies with its now-playing embed. Members who
+/// can't interrupt the current song get it queued instead.
+async fn play_track_now(ctx: Context<'_>, call_lock: &Arc<Mutex<Call>>, track: MusicTrackRow) -> Result<(), Error> {
+    if !can_control_playback(ctx, call_lock, false).await? {
+        let title = track.title.clone();
+        let song = queued_song(ctx, track);
+        let position = enqueue_song(call_lock, song, ctx.serenity_context().http.clone(), ctx.channel_id()).await;
+        ctx.say(format!(
+            "Someone else's song is playing, so **{}** was added to the queue at position {}.",
+            title, position
+        ))
+            .await?;
+        return Ok(());
+    }
+
+    let song = queued_song(ctx, track);
+    let track_id = song.track.id;
+    let (embed, cover) = with_cover_art(now_playing_embed(&song), &song.path).await;
+    play_song_now(call_lock, song).await;
+
+    let mut reply = poise::CreateReply::default()
+        .embed(embed)
+        .components(song_components(track_id, false));
+    if let Some(cover) = cover {
+        reply = reply.attachment(cover);
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+async fn join_author_channel(ctx: Context<'_>) -> Result<Option<Arc<Mutex<Call>>>, Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(None);
+        }
+    };
+
+    let Some(channel_id) = find_user_voice_channel(ctx.serenity_context(), guild_id, ctx.author().id).await else {
+        ctx.say("You must be connected to a voice channel.").await?;
+        return Ok(None);
+    };
+    if voice_quiz_running(ctx.data(), guild_id) {
+        ctx.say("A music quiz is using the voice channel right now. Try again once it's over.").await?;
+        return Ok(None);
+    }
+
+    match join_voice_channel(ctx.serenity_context(), guild_id, channel_id).await {
+        Ok(call_lock) => Ok(Some(call_lock)),
+        Err(e) => {
+            tracing::error!("Songbird join error: {}", e);
+            ctx.say("Failed to join the voice channel.").await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn active_call(ctx: Context<'_>) -> Result<Option<Arc<Mutex<Call>>>, Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(None);
+        }
+    };
+
+    match guild_call(ctx.serenity_context(), guild_id).await {
+        Some(call_lock) if !call_lock.lock().await.queue().is_empty() => Ok(Some(call_lock)),
+        _ => {
+            ctx.say("Nothing is playing right now.").await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Stream a random song into your current voice channel, replacing the current track.
+#[poise::command(slash_command, guild_only, rename = "play")]
+pub async fn music_play(
+    ctx: Context<'_>,
+    #[description = "Series folder to pick music from (default: everything)"]
+    #[autocomplete = "autocomplete_series"]
+    series: Option<String>,
+    #[description = "Entry folder within the series"]
+    #[autocomplete = "autocomplete_entry"]
+    entry: Option<String>,
+) -> Result<(), Error> {
+    let music_folder = match catalog_folder(series.as_deref(), entry.as_deref()) {
+        Ok(folder) => folder,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.defer().await?;
+
+    let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+
+    match get_random_song(&music_folder, ctx.guild_id()) {
+        Ok(Some(track)) => {
+            play_track_now(ctx, &call_lock, track).await?;
         }
         Ok(None) => {
             ctx.say(format!(
-                "Couldn't find any songs in the {} folder for Final Fantasy ({}).",
-                expansion_folder,
-                expansion.name()
+                "Couldn't find any songs in the `{}` folder.",
+                music_folder
             ))
                 .await?;
         }
@@ -117,56 +450,1358 @@ pub async fn music_ff(
     Ok(())
 }
 
-/// Get a random song from the Kingdom Hearts folder.
-#[poise::command(slash_command, rename = "kh")]
-pub async fn music_kh(
+/// Manage the voice channel music queue.
+#[poise::command(slash_command, guild_only, rename = "queue", subcommands("queue_add", "queue_list"))]
+pub async fn music_queue(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/music queue add` or `/music queue list`.").await?;
+    Ok(())
+}
+
+/// Add random songs to the end of the queue.
+#[poise::command(slash_command, guild_only, rename = "add")]
+pub async fn queue_add(
+    ctx: Context<'_>,
+    #[description = "How many songs to add (default 1)"]
+    #[min = 1]
+    #[max = 10]
+    count: Option<u8>,
+    #[description = "Series folder to pick music from (default: everything)"]
+    #[autocomplete = "autocomplete_series"]
+    series: Option<String>,
+    #[description = "Entry folder within the series"]
+    #[autocomplete = "autocomplete_entry"]
+    entry: Option<String>,
+) -> Result<(), Error> {
+    let music_folder = match catalog_folder(series.as_deref(), entry.as_deref()) {
+        Ok(folder) => folder,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.defer().await?;
+
+    let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+
+    let mut lines = Vec::new();
+    for _ in 0..count.unwrap_or(1) {
+        match get_random_song(&music_folder, ctx.guild_id()) {
+            Ok(Some(track)) => {
+                let title = track.title.clone();
+                let song = queued_song(ctx, track);
+                let position = enqueue_song(&call_lock, song, ctx.serenity_context().http.clone(), ctx.channel_id()).await;
+                if position == 0 {
+                    lines.push(format!("▶️ **{}** (playing now)", title));
+                } else {
+                    lines.push(format!("{}. **{}**", position, title));
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                tracing::error!("Error getting random song: {}", e);
+                break;
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        ctx.say(format!(
+            "Couldn't find any songs in the `{}` folder.",
+            music_folder
+        ))
+            .await?;
+        return Ok(());
+    }
+
+    let embed = CreateEmbed::new()
+        .title("🎶 Added to Queue")
+        .description(lines.join("\n"))
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Show the songs waiting in the queue.
+#[poise::command(slash_command, guild_only, rename = "list")]
+pub async fn queue_list(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(call_lock) = active_call(ctx).await? else { return Ok(()); };
+
+    let tracks = call_lock.lock().await.queue().current_queue();
+    let mut lines = Vec::new();
+    for (idx, track) in tracks.iter().take(15).enumerate() {
+        let song = track.data::<QueuedSong>();
+        if idx == 0 {
+            lines.push(format!("▶️ **{}** — <@{}>", song.track.title, song.requested_by.get()));
+        } else {
+            lines.push(format!("{}. **{}** — <@{}>", idx, song.track.title, song.requested_by.get()));
+        }
+    }
+    if tracks.len() > 15 {
+        lines.push(format!("…and {} more.", tracks.len() - 15));
+    }
+
+    let embed = CreateEmbed::new()
+        .title("🎶 Queue")
+        .description(lines.join("\n"))
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Keep playing random songs from a folder until stopped or everyone leaves.
+#[poise::command(slash_command, guild_only, rename = "radio")]
+pub async fn music_radio(
     ctx: Context<'_>,
-    #[description = "Which title to pick music from"] title: KingdomHeartsTitle,
+    #[description = "Series folder to play music from"]
+    #[autocomplete = "autocomplete_series"]
+    series: String,
+    #[description = "Entry folder within the series"]
+    #[autocomplete = "autocomplete_entry"]
+    entry: Option<String>,
 ) -> Result<(), Error> {
-    let base_folder = Game::KH.folder_name();
-    let title_folder = title.folder_name();
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    let music_folder = match catalog_folder(Some(&series), entry.as_deref()) {
+        Ok(folder) => folder,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
 
-    let base_path = env::var("MUSIC_FOLDER").expect("Expected MUSIC_FOLDER in the environment");
-    let music_dir = Path::new(&base_path).join(base_folder).join(title_folder);
+    ctx.defer().await?;
 
-    match get_random_song(&music_dir).await {
-        Ok(Some(song_path)) => {
-            let file_name = song_path
-                .file_name()
-                .map(|name| name.to_string_lossy().into_owned())
-                .unwrap_or_else(|| "unknown_song.mp3".to_string());
+    // Also starts a rescan when the folder is new, so the radio has songs to pick from soon.
+    match get_random_song(&music_folder, Some(guild_id)) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            ctx.say(format!("Couldn't find any songs in the `{}` folder.", music_folder)).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::error!("Error getting random song: {}", e);
+            ctx.say("An error occurred while trying to find a song.").await?;
+            return Ok(());
+        }
+    }
 
-            let file_content = fs::read(&song_path)
-                .await
-                .with_context(|| format!("Failed to read song file: {}", song_path.display()))?;
+    let Some(_call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
 
-            let attachment = CreateAttachment::bytes(file_content, &file_name);
+    let station = RadioStation {
+        folder: music_folder.clone(),
+        guild_id,
+        channel: ctx.channel_id(),
+        requested_by: ctx.author().id,
+    };
+    let mut sessions = ctx.data().radio_sessions.lock().await;
+    if let Some(previous) = sessions.remove(&guild_id) {
+        previous.abort();
+    }
+    let task = tokio::spawn(run_radio(ctx.serenity_context().clone(), station));
+    sessions.insert(guild_id, task.abort_handle());
+    drop(sessions);
 
-            let embed = CreateEmbed::new()
-                .title("🎶 Random Song")
-                .description(format!("Playing: **{}**", file_name))
-                .color(serenity::Colour::PURPLE);
+    let idle = get_music_settings(guild_id.get() as i64)?.radio_idle_minutes;
+    ctx.say(format!(
+        "📻 Radio on: playing songs from `{}` after whatever is queued. It stops with `/music stop` or after {} minutes with nobody listening.",
+        music_folder, idle
+    )).await?;
+    Ok(())
+}
+
+async fn stop_radio(ctx: Context<'_>) -> bool {
+    let Some(guild_id) = ctx.guild_id() else { return false };
+    match ctx.data().radio_sessions.lock().await.remove(&guild_id) {
+        Some(task) => {
+            let was_running = !task.is_finished();
+            task.abort();
+            was_running
+        }
+        None => false,
+    }
+}
+
+/// Skip the current song, or vote to skip it when others are listening.
+#[poise::command(slash_command, guild_only, rename = "skip")]
+pub async fn music_skip(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(call_lock) = active_call(ctx).await? else { return Ok(()); };
+    let guild_id = ctx.guild_id().unwrap_or_default();
+    let settings = get_music_settings(guild_id.get() as i64)?;
+    let author = ctx.author().id;
 
+    let (current, bot_channel) = {
+        let call = call_lock.lock().await;
+        (call.queue().current(), call.current_channel())
+    };
+    let Some(current) = current else {
+        ctx.say("Nothing is playing right now.").await?;
+        return Ok(());
+    };
+    let song = current.data::<QueuedSong>();
+    let title = song.track.title.clone();
+
+    // DJs and whoever requested the song skip straight away; everyone else votes.
+    if song.requested_by != author && !is_dj(ctx, settings.dj_role_id).await {
+        let listeners = bot_channel
+            .map(|channel| voice_channel_listeners(ctx.serenity_context(), guild_id, serenity::ChannelId::new(channel.0.get())))
+            .unwrap_or_default();
+        if !listeners.contains(&author) {
             ctx.send(
                 poise::CreateReply::default()
-                    .embed(embed)
-                    .attachment(attachment),
-            )
-                .await?;
+                    .content("You need to be listening in my voice channel to vote to skip.")
+                    .ephemeral(true),
+            ).await?;
+            return Ok(());
         }
-        Ok(None) => {
+
+        let required = votes_needed(listeners.len(), settings.skip_ratio);
+        let votes = {
+            let mut votes = song.skip_votes.lock().unwrap_or_else(|e| e.into_inner());
+            votes.insert(author);
+            // Votes from people who have since left no longer count.
+            votes.retain(|user| listeners.contains(user));
+            votes.len()
+        };
+
+        if votes < required {
+            ctx.say(format!("🗳️ Vote to skip **{}**: {}/{} votes.", title, votes, required)).await?;
+            return Ok(());
+        }
+    }
+
+    // Only skip if the voted-on song is still the one playing.
+    let call = call_lock.lock().await;
+    if call.queue().current().is_some_and(|track| track.uuid() == current.uuid()) {
+        let _ = call.queue().skip();
+    }
+    drop(call);
+
+    ctx.say(format!("Skipped **{}**.", title)).await?;
+    Ok(())
+}
+
+/// Whether the author may interrupt what is playing: DJs, whoever requested the current song
+/// (every queued song when `whole_queue`) and anyone listening alone.
+async fn can_control_playback(ctx: Context<'_>, call_lock: &Arc<Mutex<Call>>, whole_queue: bool) -> Result<bool, Error> {
+    let author = ctx.author().id;
+    let (requesters, bot_channel) = {
+        let call = call_lock.lock().await;
+        let queue = call.queue().current_queue();
+        let songs = if whole_queue { &queue[..] } else { &queue[..queue.len().min(1)] };
+        let requesters: Vec<serenity::UserId> = songs.iter().map(|track| track.data::<QueuedSong>().requested_by).collect();
+        (requesters, call.current_channel())
+    };
+    if requesters.iter().all(|user| *user == author) {
+        return Ok(true);
+    }
+
+    let guild_id = ctx.guild_id().unwrap_or_default();
+    if is_dj(ctx, get_music_settings(guild_id.get() as i64)?.dj_role_id).await {
+        return Ok(true);
+    }
+    let listeners = bot_channel
+        .map(|channel| voice_channel_listeners(ctx.serenity_context(), guild_id, serenity::ChannelId::new(channel.0.get())))
+        .unwrap_or_default();
+    Ok(listeners == [author])
+}
+
+/// Pause the current song.
+#[poise::command(slash_command, guild_only, rename = "pause")]
+pub async fn music_pause(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(call_lock) = active_call(ctx).await? else { return Ok(()); };
+    if !can_control_playback(ctx, &call_lock, false).await? {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Only a DJ or whoever requested this song can pause it while others are listening.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let _ = call_lock.lock().await.queue().pause();
+    ctx.say("Paused.").await?;
+    Ok(())
+}
+
+/// Resume the paused song.
+#[poise::command(slash_command, guild_only, rename = "resume")]
+pub async fn music_resume(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(call_lock) = active_call(ctx).await? else { return Ok(()); };
+    if !can_control_playback(ctx, &call_lock, false).await? {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Only a DJ or whoever requested this song can resume it while others are listening.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let _ = call_lock.lock().await.queue().resume();
+    ctx.say("Resumed.").await?;
+    Ok(())
+}
+
+/// Stop playback and clear the queue.
+#[poise::command(slash_command, guild_only, rename = "stop")]
+pub async fn music_stop(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap_or_default();
+    if let Some(call_lock) = guild_call(ctx.serenity_context(), guild_id).await
+        && !can_control_playback(ctx, &call_lock, true).await?
+    {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Only a DJ can clear a queue with other people's songs while others are listening. Use `/music skip` to vote instead.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let radio_stopped = stop_radio(ctx).await;
+    let Some(call_lock) = active_call(ctx).await? else { return Ok(()); };
+
+    call_lock.lock().await.queue().stop();
+    if radio_stopped {
+        ctx.say("Stopped the radio and cleared the queue.").await?;
+    } else {
+        ctx.say("Stopped playback and cleared the queue.").await?;
+    }
+    Ok(())
+}
+
+/// Show the song that is currently playing.
+#[poise::command(slash_command, guild_only, rename = "nowplaying")]
+pub async fn music_nowplaying(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(call_lock) = active_call(ctx).await? else { return Ok(()); };
+
+    let Some(track) = call_lock.lock().await.queue().current() else {
+        ctx.say("Nothing is playing right now.").await?;
+        return Ok(());
+    };
+
+    let song = track.data::<QueuedSong>();
+    let (mut embed, cover) = with_cover_art(now_playing_embed(&song), &song.path).await;
+    if let Ok(info) = track.get_info().await {
+        let paused = if matches!(info.playing, songbird::tracks::PlayMode::Pause) { " (paused)" } else { "" };
+        embed = embed.field("Position", format!("{}{}", format_position(info.position), paused), true);
+    }
+
+    let mut reply = poise::CreateReply::default()
+        .embed(embed)
+        .components(song_components(song.track.id, false));
+    if let Some(cover) = cover {
+        reply = reply.attachment(cover);
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Your favourite songs.
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "favorites",
+    subcommands("favorites_list", "favorites_play")
+)]
+pub async fn music_favorites(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/music favorites list` or `/music favorites play`.").await?;
+    Ok(())
+}
+
+/// List your favourite songs (or someone else's).
+#[poise::command(slash_command, guild_only, rename = "list")]
+pub async fn favorites_list(
+    ctx: Context<'_>,
+    #[description = "Whose favourites to show (default: yours)"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    let user = user.as_ref().unwrap_or_else(|| ctx.author());
+
+    let favorites = get_music_favorites(guild_id.get() as i64, user.id.get() as i64)?;
+    if favorites.is_empty() {
+        let msg = if user.id == ctx.author().id {
+            "You have no favourites yet. Press ❤️ Favourite on a song to add it.".to_string()
+        } else {
+            format!("{} has no favourites yet.", user.name)
+        };
+        ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+        return Ok(());
+    }
+
+    let mut lines: Vec<String> = favorites
+        .iter()
+        .take(30)
+        .enumerate()
+        .map(|(idx, track)| match &track.artist {
+            Some(artist) => format!("{}. **{}** — {}", idx + 1, track.title, artist),
+            None => format!("{}. **{}**", idx + 1, track.title),
+        })
+        .collect();
+    if favorites.len() > 30 {
+        lines.push(format!("…and {} more.", favorites.len() - 30));
+    }
+
+    let embed = CreateEmbed::new()
+        .title(format!("❤️ {}'s Favourites", user.name))
+        .description(lines.join("\n"))
+        .footer(serenity::CreateEmbedFooter::new("Play them with /music favorites play"))
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Add your favourite songs to the voice queue.
+#[poise::command(slash_command, guild_only, rename = "play")]
+pub async fn favorites_play(
+    ctx: Context<'_>,
+    #[description = "Shuffle the songs? (default: yes)"] shuffle: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    let mut favorites = get_music_favorites(guild_id.get() as i64, ctx.author().id.get() as i64)?;
+    if favorites.is_empty() {
+        ctx.say("You have no favourites yet. Press ❤️ Favourite on a song to add it.").await?;
+        return Ok(());
+    }
+    if shuffle.unwrap_or(true) {
+        favorites.shuffle(&mut rand::rng());
+    }
+
+    ctx.defer().await?;
+
+    let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+
+    let count = favorites.len();
+    let mut first_position = None;
+    for track in favorites {
+        let song = queued_song(ctx, track);
+        let position = enqueue_song(&call_lock, song, ctx.serenity_context().http.clone(), ctx.channel_id()).await;
+        first_position.get_or_insert(position);
+    }
+
+    let msg = match first_position.unwrap_or_default() {
+        0 => format!("▶️ Playing your {} favourites.", count),
+        position => format!("Added your {} favourites to the queue from position {}.", count, position),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Show this server's highest-rated songs.
+#[poise::command(slash_command, guild_only, rename = "top")]
+pub async fn music_top(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    let top = get_top_rated_tracks(guild_id.get() as i64, 10)?;
+    let description = if top.is_empty() {
+        "No songs have been rated yet. Use the \"Rate this song\" menu on a song to rate it.".to_string()
+    } else {
+        top.iter()
+            .enumerate()
+            .map(|(idx, rated)| {
+                let favorites = if rated.favorites > 0 { format!(" · ❤️ {}", rated.favorites) } else { String::new() };
+                format!(
+                    "{}. **{}** — ⭐ {:.1} ({} {}){}",
+                    idx + 1,
+                    rated.track.title,
+                    rated.average,
+                    rated.ratings,
+                    if rated.ratings == 1 { "rating" } else { "ratings" },
+                    favorites
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let embed = CreateEmbed::new()
+        .title("🏆 Top Rated Songs")
+        .description(description)
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Show what this server (or one member) has been listening to.
+#[poise::command(slash_command, guild_only, rename = "stats")]
+pub async fn music_stats(
+    ctx: Context<'_>,
+    #[description = "Time span to cover (default: the last 7 days)"] period: Option<StatsPeriod>,
+    #[description = "Only count songs this member asked for"] user: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    let period = period.unwrap_or(StatsPeriod::Week);
+    let since = period.since();
+    let stats = get_music_stats(
+        guild_id.get() as i64,
+        since.as_deref(),
+        user.as_ref().map(|u| u.id.get() as i64),
+        5,
+    )?;
+
+    let heading = match &user {
+        Some(user) => format!("📊 {}'s Music Stats", user.display_name()),
+        None => "📊 Music Stats".to_string(),
+    };
+    let embed = stats_embed(&stats, &heading).footer(serenity::CreateEmbedFooter::new(period.name()));
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Guess-the-song quiz.
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "quiz",
+    subcommands("quiz_start", "quiz_stop", "quiz_leaderboard")
+)]
+pub async fn music_quiz(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/music quiz start`, `/music quiz stop` or `/music quiz leaderboard`.").await?;
+    Ok(())
+}
+
+/// Start a guess-the-song quiz in this channel.
+#[poise::command(slash_command, guild_only, rename = "start")]
+pub async fn quiz_start(
+    ctx: Context<'_>,
+    #[description = "Number of rounds (default 5)"]
+    #[min = 1]
+    #[max = 20]
+    rounds: Option<u8>,
+    #[description = "Only play songs from this series (default: everything)"]
+    #[autocomplete = "autocomplete_series"]
+    series: Option<String>,
+    #[description = "Only play songs from this entry of the series"]
+    #[autocomplete = "autocomplete_entry"]
+    entry: Option<String>,
+    #[description = "What players have to name (default: series, or entry/title when limited)"] guess: Option<QuizGuess>,
+    #[description = "How clips are played (default: in voice if you're in a voice channel)"] medium: Option<QuizMedium>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    let music_folder = match catalog_folder(series.as_deref(), entry.as_deref()) {
+        Ok(folder) => folder,
+        Err(msg) => {
+            ctx.say(msg).await?;
+            return Ok(());
+        }
+    };
+
+    let pool = get_music_tracks_in_folder(&music_folder)?;
+    let guess = match guess {
+        Some(guess) => guess,
+        None => {
+            let preferred = match (&series, &entry) {
+                (None, _) => QuizGuess::Series,
+                (Some(_), None) => QuizGuess::Entry,
+                (Some(_), Some(_)) => QuizGuess::Title,
+            };
+            if distinct_answers(&pool, preferred) >= 2 { preferred } else { QuizGuess::Title }
+        }
+    };
+    if distinct_answers(&pool, guess) < 2 {
+        ctx.say(format!(
+            "There aren't enough different {} answers in that part of the library for a quiz.",
+            guess.noun()
+        ))
+            .await?;
+        return Ok(());
+    }
+
+    let in_voice = find_user_voice_channel(ctx.serenity_context(), guild_id, ctx.author().id).await.is_some();
+    let medium = medium.unwrap_or(if in_voice { QuizMedium::Voice } else { QuizMedium::Attachment });
+
+    let call = if medium == QuizMedium::Voice {
+        let radio_on = ctx.data().radio_sessions.lock().await.get(&guild_id).is_some_and(|task| !task.is_finished());
+        let music_queued = match guild_call(ctx.serenity_context(), guild_id).await {
+            Some(call_lock) => !call_lock.lock().await.queue().is_empty(),
+            None => false,
+        };
+        if radio_on || music_queued {
+            ctx.say("Music is playing in voice right now. Stop it with `/music stop` before starting a quiz.").await?;
+            return Ok(());
+        }
+        let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+        Some(call_lock)
+    } else {
+        None
+    };
+
+    // Held until the quiz ends, including on errors.
+    let Some(slot) = QuizSlot::claim(ctx.data(), ctx.channel_id(), guild_id, call.is_some()) else {
+        ctx.say("A quiz is already running in this channel.").await?;
+        return Ok(());
+    };
+
+    let rounds = rounds.unwrap_or(5);
+    ctx.say(format!(
+        "🎧 Starting a {}-round music quiz! Name the {} of each clip.",
+        rounds,
+        guess.noun()
+    ))
+        .await?;
+
+    run_quiz(ctx, QuizSettings { pool, rounds, guess, medium, call }, &slot.run).await
+}
+
+/// Stop the quiz running in this channel after the current round.
+#[poise::command(slash_command, guild_only, rename = "stop")]
+pub async fn quiz_stop(ctx: Context<'_>) -> Result<(), Error> {
+    let run = ctx.data().quiz_channels.lock().unwrap_or_else(|e| e.into_inner()).get(&ctx.channel_id()).cloned();
+    if let Some(run) = run {
+        run.stop.store(true, Ordering::SeqCst);
+        ctx.say("The quiz will stop after this round.").await?;
+    } else {
+        ctx.send(poise::CreateReply::default().content("No quiz is running in this channel.").ephemeral(true)).await?;
+    }
+    Ok(())
+}
+
+/// Show the best quiz players of this server.
+#[poise::command(slash_command, guild_only, rename = "leaderboard")]
+pub async fn quiz_leaderboard(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    let scores = get_quiz_leaderboard(guild_id.get() as i64, 10)?;
+    let description = if scores.is_empty() {
+        "Nobody has scored yet. Start a game with `/music quiz start`.".to_string()
+    } else {
+        scores
+            .iter()
+            .enumerate()
+            .map(|(idx, (user_id, points))| {
+                let medal = match idx {
+                    0 => "🥇".to_string(),
+                    1 => "🥈".to_string(),
+                    2 => "🥉".to_string(),
+                    _ => format!("{}.", idx + 1),
+                };
+                format!("{} <@{}> — {} pts", medal, user_id, points)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let embed = CreateEmbed::new()
+        .title("🏆 Music Quiz Leaderboard")
+        .description(description)
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Re-index the music folder (administrators only).
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "rescan",
+    required_permissions = "ADMINISTRATOR"
+)]
+pub async fn music_rescan(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+
+    match scan_library().await {
+        Ok(Some(summary)) => {
             ctx.say(format!(
-                "Couldn't find any songs in the {} folder for Kingdom Hearts ({}).",
-                title_folder,
-                title.name()
+                "Music library rescanned: {} tracks ({} added, {} updated, {} removed), {} playlists. \
+                 New tracks are analysed for loudness in the background.",
+                summary.total, summary.added, summary.updated, summary.removed, summary.playlists
             ))
                 .await?;
         }
+        Ok(None) => {
+            ctx.say("A library scan is already running, try again in a moment.").await?;
+        }
         Err(e) => {
-            tracing::error!("Error getting random song: {}", e);
-            ctx.say("An error occurred while trying to find a song.")
-                .await?;
+            tracing::error!("Music library scan failed: {}", e);
+            ctx.say("Failed to rescan the music library.").await?;
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Configure music for this server (administrators only).
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "settings",
+    required_permissions = "ADMINISTRATOR",
+    subcommands(
+        "settings_show",
+        "settings_selection",
+        "settings_ratings",
+        "settings_voteskip",
+        "settings_dj",
+        "settings_radio"
+    )
+)]
+pub async fn music_settings(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/music settings show` or `/music settings selection`.").await?;
+    Ok(())
+}
+
+/// Show this server's music settings.
+#[poise::command(slash_command, guild_only, rename = "show", required_permissions = "ADMINISTRATOR")]
+pub async fn settings_show(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    let settings = get_music_settings(guild_id.get() as i64)?;
+    let mode = SelectionMode::from_db(&settings.selection_mode);
+
+    let embed = CreateEmbed::new()
+        .title("🎛️ Music Settings")
+        .field("Random selection", mode.name(), true)
+        .field("Recent songs skipped", settings.history_size.to_string(), true)
+        .field("Prefer well-rated songs", if settings.prefer_rated { "Yes" } else { "No" }, true)
+        .field("Votes needed to skip", format!("{}% of listeners", settings.skip_ratio), true)
+        .field("DJ role", settings.dj_role_id.map(|id| format!("<@&{}>", id)).unwrap_or_else(|| "None".to_string()), true)
+        .field("Radio stops after", format!("{} minutes without listeners", settings.radio_idle_minutes), true)
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Choose how random songs are picked to avoid repeats.
+#[poise::command(slash_command, guild_only, rename = "selection", required_permissions = "ADMINISTRATOR")]
+pub async fn settings_selection(
+    ctx: Context<'_>,
+    #[description = "How random songs are picked"] mode: SelectionMode,
+    #[description = "How many recently played songs to skip (default 25)"]
+    #[min = 1]
+    #[max = 500]
+    history: Option<i64>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    let current = get_music_settings(guild_id.get() as i64)?;
+    let history = history.unwrap_or(current.history_size);
+
+    if let Err(e) = set_music_selection(guild_id.get() as i64, mode.as_str(), history) {
+        tracing::error!("Failed to save music settings: {}", e);
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Failed to save the music settings. Please try again later.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let msg = match mode {
+        SelectionMode::AvoidRecent => format!("Random songs now skip the last {} songs played here.", history),
+        _ => format!("Random selection set to: {}.", mode.name()),
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+const PLAYLIST_MAX_TRACKS: i64 = 200;
+
+/// Save songs from the library into your own playlists.
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "playlist_create",
+        "playlist_add",
+        "playlist_remove",
+        "playlist_show",
+        "playlist_play",
+        "playlist_delete"
+    )
+)]
+pub async fn playlist(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/playlist create`, `/playlist add`, `/playlist show` or `/playlist play`.").await?;
+    Ok(())
+}
+
+/// Create a new playlist.
+#[poise::command(slash_command, guild_only, rename = "create")]
+pub async fn playlist_create(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist"]
+    #[max_length = 50]
+    name: String,
+    #[description = "Let everyone in this server see and play it? (default: no)"] shared: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        ctx.send(poise::CreateReply::default().content("Please give the playlist a name.").ephemeral(true)).await?;
+        return Ok(());
+    }
+
+    let shared = shared.unwrap_or(false);
+    let msg = match create_playlist(guild_id.get() as i64, ctx.author().id.get() as i64, name, shared) {
+        Ok(true) if shared => format!("Created the shared playlist **{}**. Add songs with `/playlist add`.", name),
+        Ok(true) => format!("Created the playlist **{}**. Add songs with `/playlist add`.", name),
+        Ok(false) => format!("You already have a playlist called **{}**.", name),
+        Err(e) => {
+            tracing::error!("Failed to create playlist: {}", e);
+            "Failed to create the playlist. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Add a song from the library to one of your playlists.
+#[poise::command(slash_command, guild_only, rename = "add")]
+pub async fn playlist_add(
+    ctx: Context<'_>,
+    #[description = "Your playlist"]
+    #[autocomplete = "autocomplete_own_playlist"]
+    playlist: String,
+    #[description = "Song title, artist, album or file name"]
+    #[autocomplete = "autocomplete_track"]
+    song: String,
+) -> Result<(), Error> {
+    let Some(playlist) = own_playlist(ctx, &playlist).await? else { return Ok(()); };
+
+    if playlist.track_count >= PLAYLIST_MAX_TRACKS {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("**{}** is full ({} songs).", playlist.name, PLAYLIST_MAX_TRACKS))
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let track = match resolve_track(&song) {
+        Ok(Some(track)) => track,
+        Ok(None) => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!("Couldn't find any songs matching '{}'.", song.trim()))
+                    .ephemeral(true),
+            ).await?;
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::error!("Error searching songs: {}", e);
+            ctx.send(poise::CreateReply::default().content("An error occurred while searching for songs.").ephemeral(true)).await?;
+            return Ok(());
+        }
+    };
+
+    let msg = match add_playlist_track(playlist.id, track.id) {
+        Ok(()) => format!(
+            "Added **{}** to **{}** (position {}).",
+            track.title,
+            playlist.name,
+            playlist.track_count + 1
+        ),
+        Err(e) => {
+            tracing::error!("Failed to add song to playlist: {}", e);
+            "Failed to add the song. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Remove a song from one of your playlists by its position.
+#[poise::command(slash_command, guild_only, rename = "remove")]
+pub async fn playlist_remove(
+    ctx: Context<'_>,
+    #[description = "Your playlist"]
+    #[autocomplete = "autocomplete_own_playlist"]
+    playlist: String,
+    #[description = "Position of the song (see /playlist show)"]
+    #[min = 1]
+    position: i64,
+) -> Result<(), Error> {
+    let Some(playlist) = own_playlist(ctx, &playlist).await? else { return Ok(()); };
+
+    let msg = match remove_playlist_track(playlist.id, position) {
+        Ok(true) => format!("Removed song {} from **{}**.", position, playlist.name),
+        Ok(false) => format!("**{}** has no song at position {}.", playlist.name, position),
+        Err(e) => {
+            tracing::error!("Failed to remove song from playlist: {}", e);
+            "Failed to remove the song. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Show a playlist's songs, or list the playlists you can play.
+#[poise::command(slash_command, guild_only, rename = "show")]
+pub async fn playlist_show(
+    ctx: Context<'_>,
+    #[description = "Playlist to show (default: list all playlists)"]
+    #[autocomplete = "autocomplete_playlist"]
+    playlist: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    let user_id = ctx.author().id.get() as i64;
+
+    let Some(name) = playlist else {
+        let playlists = get_visible_playlists(guild_id.get() as i64, user_id)?;
+        if playlists.is_empty() {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content("There are no playlists yet. Create one with `/playlist create`.")
+                    .ephemeral(true),
+            ).await?;
+            return Ok(());
+        }
+
+        let lines: Vec<String> = playlists
+            .iter()
+            .map(|p| {
+                let owner = if p.owner_id == user_id { String::new() } else { format!(" by <@{}>", p.owner_id) };
+                let shared = if p.shared { " (shared)" } else { "" };
+                format!("- **{}**{}{} — {} songs", p.name, owner, shared, p.track_count)
+            })
+            .collect();
+        let embed = CreateEmbed::new()
+            .title("📜 Playlists")
+            .description(lines.join("\n"))
+            .color(serenity::Colour::PURPLE);
+        ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let Some(playlist) = visible_playlist(ctx, &name).await? else { return Ok(()); };
+    let tracks = get_playlist_tracks(playlist.id)?;
+
+    let mut lines: Vec<String> = tracks
+        .iter()
+        .take(30)
+        .enumerate()
+        .map(|(idx, track)| match &track.artist {
+            Some(artist) => format!("{}. **{}** — {}", idx + 1, track.title, artist),
+            None => format!("{}. **{}**", idx + 1, track.title),
+        })
+        .collect();
+    if tracks.len() > 30 {
+        lines.push(format!("…and {} more.", tracks.len() - 30));
+    }
+    if lines.is_empty() {
+        lines.push("No songs yet. Add some with `/playlist add`.".to_string());
+    }
+
+    let total_secs: i64 = tracks.iter().filter_map(|t| t.duration_secs).sum();
+    let embed = CreateEmbed::new()
+        .title(format!("📜 {}", playlist.name))
+        .description(lines.join("\n"))
+        .field("Owner", format!("<@{}>", playlist.owner_id), true)
+        .field("Songs", tracks.len().to_string(), true)
+        .field("Length", format_position(std::time::Duration::from_secs(total_secs as u64)), true)
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(!playlist.shared)).await?;
+    Ok(())
+}
+
+/// Add every song of a playlist to the voice queue.
+#[poise::command(slash_command, guild_only, rename = "play")]
+pub async fn playlist_play(
+    ctx: Context<'_>,
+    #[description = "Playlist to play"]
+    #[autocomplete = "autocomplete_playlist"]
+    playlist: String,
+    #[description = "Shuffle the songs? (default: no)"] shuffle: Option<bool>,
+) -> Result<(), Error> {
+    let Some(playlist) = visible_playlist(ctx, &playlist).await? else { return Ok(()); };
+
+    let mut tracks = get_playlist_tracks(playlist.id)?;
+    if tracks.is_empty() {
+        ctx.say(format!("**{}** has no songs yet. Add some with `/playlist add`.", playlist.name)).await?;
+        return Ok(());
+    }
+    if shuffle.unwrap_or(false) {
+        tracks.shuffle(&mut rand::rng());
+    }
+
+    ctx.defer().await?;
+
+    let Some(call_lock) = join_author_channel(ctx).await? else { return Ok(()); };
+
+    let count = tracks.len();
+    let mut first_position = None;
+    for track in tracks {
+        let song = queued_song(ctx, track);
+        let position = enqueue_song(&call_lock, song, ctx.serenity_context().http.clone(), ctx.channel_id()).await;
+        first_position.get_or_insert(position);
+    }
+
+    let msg = match first_position.unwrap_or_default() {
+        0 => format!("▶️ Playing **{}** ({} songs).", playlist.name, count),
+        position => format!("Added **{}** ({} songs) to the queue from position {}.", playlist.name, count, position),
+    };
+    ctx.say(msg).await?;
+    Ok(())
+}
+
+/// Delete one of your playlists.
+#[poise::command(slash_command, guild_only, rename = "delete")]
+pub async fn playlist_delete(
+    ctx: Context<'_>,
+    #[description = "Your playlist"]
+    #[autocomplete = "autocomplete_own_playlist"]
+    playlist: String,
+) -> Result<(), Error> {
+    let Some(playlist) = own_playlist(ctx, &playlist).await? else { return Ok(()); };
+
+    let msg = match delete_playlist_owned(playlist.id, ctx.author().id.get() as i64) {
+        Ok(true) => format!("Deleted the playlist **{}**.", playlist.name),
+        Ok(false) => "That playlist doesn't belong to you.".to_string(),
+        Err(e) => {
+            tracing::error!("Failed to delete playlist: {}", e);
+            "Failed to delete the playlist. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+async fn visible_playlist(ctx: Context<'_>, name: &str) -> Result<Option<PlaylistRow>, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("This command can only be used in a server.").await?;
+        return Ok(None);
+    };
+
+    match find_playlist(guild_id.get() as i64, ctx.author().id.get() as i64, name.trim())? {
+        Some(playlist) => Ok(Some(playlist)),
+        None => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!("Couldn't find a playlist called **{}**.", name.trim()))
+                    .ephemeral(true),
+            ).await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn own_playlist(ctx: Context<'_>, name: &str) -> Result<Option<PlaylistRow>, Error> {
+    let Some(playlist) = visible_playlist(ctx, name).await? else { return Ok(None); };
+    if playlist.owner_id != ctx.author().id.get() as i64 {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("**{}** belongs to <@{}>; only they can change it.", playlist.name, playlist.owner_id))
+                .ephemeral(true),
+        ).await?;
+        return Ok(None);
+    }
+    Ok(Some(playlist))
+}
+
+async fn autocomplete_playlist(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    playlist_choices(ctx, partial, false)
+}
+
+async fn autocomplete_own_playlist(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    playlist_choices(ctx, partial, true)
+}
+
+fn playlist_choices(ctx: Context<'_>, partial: &str, own_only: bool) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else { return Vec::new(); };
+    let user_id = ctx.author().id.get() as i64;
+    let partial = partial.to_lowercase();
+
+    match get_visible_playlists(guild_id.get() as i64, user_id) {
+        Ok(playlists) => playlists
+            .into_iter()
+            .filter(|p| !own_only || p.owner_id == user_id)
+            .filter(|p| p.name.to_lowercase().contains(&partial))
+            .map(|p| p.name)
+            .take(25)
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Playlist autocomplete failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub async fn handle_music_component(
+    ctx: &serenity::Context,
+    component: &serenity::ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    let custom_id = component.data.custom_id.as_str();
+    let parse = |prefix: &str| custom_id.strip_prefix(prefix).and_then(|id| id.parse::<i64>().ok());
+
+    let result = if let Some(track_id) = parse(PLAY_BUTTON_PREFIX) {
+        play_from_button(ctx, component, data, track_id).await
+    } else if let Some(track_id) = parse(FAVORITE_BUTTON_PREFIX) {
+        favorite_from_button(component, track_id)
+    } else if let Some(track_id) = parse(RATING_MENU_PREFIX) {
+        rate_from_menu(component, track_id)
+    } else {
+        return Ok(());
+    };
+
+    let msg = match result {
+        Ok(msg) => msg,
+        Err(e) => {
+            tracing::error!("Music component {} failed: {}", custom_id, e);
+            "Something went wrong, please try again later.".to_string()
+        }
+    };
+    component
+        .create_response(
+            &ctx.http,
+            serenity::CreateInteractionResponse::Message(
+                serenity::CreateInteractionResponseMessage::new().content(msg).ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+fn favorite_from_button(component: &serenity::ComponentInteraction, track_id: i64) -> Result<String, Error> {
+    let Some(track) = get_music_track(track_id)? else {
+        return Ok("That song is no longer in the music library.".to_string());
+    };
+    let guild_id = component.guild_id.map(|g| g.get() as i64).unwrap_or_default();
+    Ok(if toggle_music_favorite(guild_id, component.user.id.get() as i64, track_id)? {
+        format!("❤️ Added **{}** to your favourites. See them with `/music favorites list`.", track.title)
+    } else {
+        format!("Removed **{}** from your favourites.", track.title)
+    })
+}
+
+fn rate_from_menu(component: &serenity::ComponentInteraction, track_id: i64) -> Result<String, Error> {
+    let rating = match &component.data.kind {
+        serenity::ComponentInteractionDataKind::StringSelect { values } => {
+            values.first().and_then(|v| v.parse::<i64>().ok()).filter(|r| (1..=5).contains(r))
+        }
+        _ => None,
+    };
+    let Some(rating) = rating else {
+        return Ok("Pick between one and five stars.".to_string());
+    };
+    let Some(track) = get_music_track(track_id)? else {
+        return Ok("That song is no longer in the music library.".to_string());
+    };
+
+    let guild_id = component.guild_id.map(|g| g.get() as i64).unwrap_or_default();
+    set_music_rating(guild_id, component.user.id.get() as i64, track_id, rating)?;
+    Ok(format!("You rated **{}** {}.", track.title, "⭐".repeat(rating as usize)))
+}
+
+async fn play_from_button(
+    ctx: &serenity::Context,
+    component: &serenity::ComponentInteraction,
+    data: &Data,
+    track_id: i64,
+) -> Result<String, Error> {
+    let Some(guild_id) = component.guild_id else {
+        return Ok("Songs can only be played in a server.".to_string());
+    };
+    let Some(track) = get_music_track(track_id)? else {
+        return Ok("That song is no longer in the music library.".to_string());
+    };
+    let Some(channel_id) = find_user_voice_channel(ctx, guild_id, component.user.id).await else {
+        return Ok("You must be connected to a voice channel.".to_string());
+    };
+    if voice_quiz_running(data, guild_id) {
+        return Ok("A music quiz is using the voice channel right now. Try again once it's over.".to_string());
+    }
+
+    let call_lock = join_voice_channel(ctx, guild_id, channel_id).await?;
+    let title = track.title.clone();
+    let song = QueuedSong::new(track, guild_id, component.user.id, "play button");
+    let position = enqueue_song(&call_lock, song, ctx.http.clone(), component.channel_id).await;
+    Ok(if position == 0 {
+        format!("▶️ Playing **{}** now.", title)
+    } else {
+        format!("Added **{}** to the queue at position {}.", title, position)
+    })
+}
+
+/// Choose whether random songs favour well-rated ones.
+#[poise::command(slash_command, guild_only, rename = "ratings", required_permissions = "ADMINISTRATOR")]
+pub async fn settings_ratings(
+    ctx: Context<'_>,
+    #[description = "Make highly rated songs more likely and poorly rated ones less likely"] prefer: bool,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = set_music_prefer_rated(guild_id.get() as i64, prefer) {
+        tracing::error!("Failed to save music settings: {}", e);
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Failed to save the music settings. Please try again later.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let msg = if prefer {
+        "Random songs now favour well-rated songs."
+    } else {
+        "Random songs no longer take ratings into account."
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Set how many listeners have to vote before a song is skipped.
+#[poise::command(slash_command, guild_only, rename = "voteskip", required_permissions = "ADMINISTRATOR")]
+pub async fn settings_voteskip(
+    ctx: Context<'_>,
+    #[description = "Percentage of listeners in the voice channel that must vote (default 50)"]
+    #[min = 1]
+    #[max = 100]
+    percent: u8,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = set_music_skip_ratio(guild_id.get() as i64, percent as i64) {
+        tracing::error!("Failed to save music settings: {}", e);
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Failed to save the music settings. Please try again later.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let msg = format!("Skipping a song now needs votes from {}% of listeners.", percent);
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Set the DJ role whose members can skip without a vote.
+#[poise::command(slash_command, guild_only, rename = "dj", required_permissions = "ADMINISTRATOR")]
+pub async fn settings_dj(
+    ctx: Context<'_>,
+    #[description = "Role that can skip songs without a vote (leave empty to remove)"] role: Option<serenity::Role>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = set_music_dj_role(guild_id.get() as i64, role.as_ref().map(|r| r.id.get() as i64)) {
+        tracing::error!("Failed to save music settings: {}", e);
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Failed to save the music settings. Please try again later.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let msg = match role {
+        Some(role) => format!("Members with <@&{}> can now skip songs without a vote.", role.id),
+        None => "The DJ role has been removed; every skip now goes to a vote.".to_string(),
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+/// Set how long the radio keeps playing to an empty voice channel.
+#[poise::command(slash_command, guild_only, rename = "radio", required_permissions = "ADMINISTRATOR")]
+pub async fn settings_radio(
+    ctx: Context<'_>,
+    #[description = "Minutes without listeners before the radio stops (default 5)"]
+    #[min = 1]
+    #[max = 120]
+    minutes: u8,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = set_music_radio_idle(guild_id.get() as i64, minutes as i64) {
+        tracing::error!("Failed to save music settings: {}", e);
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Failed to save the music settings. Please try again later.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    let msg = format!("The radio now stops after {} minutes with nobody listening.", minutes);
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
@@ -0,0 +1,490 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::picture::{MimeType, PictureType};
+use lofty::tag::{Accessor, ItemKey};
+use tokio::task;
+use walkdir::WalkDir;
+use crate::music::utils::ffmpeg_bin;
+use crate::shared::db::{
+    get_all_music_tracks, get_music_track_stamps, get_tracks_without_loudness, save_music_collections, save_music_tracks,
+    set_music_track_gain, MusicTrackRow,
+};
+use crate::shared::types::Error;
+
+pub const AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "opus", "wav", "flac", "m4a"];
+
+pub const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8"];
+
+static SCAN_RUNNING: AtomicBool = AtomicBool::new(false);
+static ANALYSIS_RUNNING: AtomicBool = AtomicBool::new(false);
+static LAST_REQUESTED_SCAN: Mutex<Option<Instant>> = Mutex::new(None);
+static INDEXED_TRACKS: Mutex<Option<Arc<Vec<MusicTrackRow>>>> = Mutex::new(None);
+
+const REQUESTED_SCAN_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Default)]
+pub struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub total: usize,
+    pub playlists: usize,
+}
+
+/// Loudness tracks are normalised to, in LUFS (the ReplayGain 2.0 reference level).
+pub const TARGET_LUFS: f64 = -18.0;
+
+pub fn music_root() -> PathBuf {
+    PathBuf::from(std::env::var("MUSIC_FOLDER").expect("Expected MUSIC_FOLDER in the environment"))
+}
+
+pub fn track_path(track: &MusicTrackRow) -> PathBuf {
+    music_root().join(&track.path)
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    has_extension(path, AUDIO_EXTENSIONS)
+}
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    has_extension(path, PLAYLIST_EXTENSIONS)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.contains(&ext.as_str()))
+}
+
+fn is_collection(entry: &walkdir::DirEntry) -> bool {
+    entry.file_type().is_dir() || (entry.file_type().is_file() && is_playlist_file(entry.path()))
+}
+
+pub async fn list_series() -> Vec<String> {
+    let root = music_root();
+    task::spawn_blocking(move || {
+        let mut series: Vec<String> = WalkDir::new(&root)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(is_collection)
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .collect();
+        series.sort();
+        series
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Every folder and playlist file below a series, relative to it (e.g. `XIV/Endwalker` under
+/// `Final Fantasy`).
+pub async fn list_entries(series: &str) -> Vec<String> {
+    if !is_plain_relative(series) {
+        return Vec::new();
+    }
+    let series_dir = music_root().join(series);
+    task::spawn_blocking(move || {
+        let mut entries: Vec<String> = WalkDir::new(&series_dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(is_collection)
+            .filter_map(|e| e.path().strip_prefix(&series_dir).ok().map(|p| p.to_string_lossy().into_owned()))
+            .collect();
+        entries.sort();
+        entries
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Turns a series/entry pick into a folder or playlist file relative to MUSIC_FOLDER (empty for
+/// the whole library), or a message explaining why it can't be used.
+pub fn catalog_folder(series: Option<&str>, entry: Option<&str>) -> Result<String, &'static str> {
+    let folder = match (series, entry) {
+        (None, None) => return Ok(String::new()),
+        (None, Some(_)) => return Err("Pick a series before choosing an entry."),
+        (Some(series), None) => series.to_string(),
+        (Some(series), Some(entry)) => format!("{}/{}", series, entry),
+    };
+
+    let path = music_root().join(&folder);
+    if !is_plain_relative(&folder) || !(path.is_dir() || (path.is_file() && is_playlist_file(&path))) {
+        return Err("That folder doesn't exist in the music library.");
+    }
+    Ok(folder)
+}
+
+/// Guards user-supplied folder names against escaping MUSIC_FOLDER.
+fn is_plain_relative(folder: &str) -> bool {
+    !folder.is_empty() && Path::new(folder).components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Brings the `music_tracks` index in line with MUSIC_FOLDER. Only new or modified files
+/// (by size and mtime) are re-read. Returns `None` if another scan is already running.
+/// Loudness analysis of new tracks continues in the background afterwards.
+pub async fn scan_library() -> Result<Option<ScanSummary>, Error> {
+    if SCAN_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(None);
+    }
+    let result = task::spawn_blocking(|| scan_library_blocking(&music_root())).await;
+    SCAN_RUNNING.store(false, Ordering::SeqCst);
+    forget_indexed_tracks();
+    let summary = result??;
+    spawn_loudness_analysis();
+    Ok(Some(summary))
+}
+
+/// Every indexed track, read once and kept until the index changes. Song search scores all of
+/// them on each autocomplete keystroke.
+pub fn indexed_tracks() -> Result<Arc<Vec<MusicTrackRow>>, Error> {
+    let mut cached = INDEXED_TRACKS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(tracks) = cached.as_ref() {
+        return Ok(tracks.clone());
+    }
+    let tracks = Arc::new(get_all_music_tracks()?);
+    *cached = Some(tracks.clone());
+    Ok(tracks)
+}
+
+fn forget_indexed_tracks() {
+    *INDEXED_TRACKS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Scans the library in the background for songs added since the last scan, at most once every
+/// [`REQUESTED_SCAN_INTERVAL`].
+pub fn request_scan() {
+    {
+        let mut last = LAST_REQUESTED_SCAN.lock().unwrap_or_else(|e| e.into_inner());
+        if last.is_some_and(|at| at.elapsed() < REQUESTED_SCAN_INTERVAL) {
+            return;
+        }
+        *last = Some(Instant::now());
+    }
+    tokio::spawn(async {
+        if let Err(e) = scan_library().await {
+            tracing::error!("Music library scan failed: {}", e);
+        }
+    });
+}
+
+/// Starts analysing tracks that still need a loudness measurement, unless that is already
+/// running. ffmpeg takes a while per track, so this doesn't hold up scans.
+fn spawn_loudness_analysis() {
+    if ANALYSIS_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tokio::spawn(async {
+        let result = task::spawn_blocking(|| analyze_loudness(&music_root())).await;
+        ANALYSIS_RUNNING.store(false, Ordering::SeqCst);
+        forget_indexed_tracks();
+        match result {
+            Ok(Ok(0)) => {}
+            Ok(Ok(analyzed)) => tracing::info!("Analysed the loudness of {} tracks", analyzed),
+            Ok(Err(e)) => tracing::error!("Loudness analysis failed: {}", e),
+            Err(e) => tracing::error!("Loudness analysis task failed: {}", e),
+        }
+    });
+}
+
+fn scan_library_blocking(root: &Path) -> Result<ScanSummary, Error> {
+    // Don't wipe the index because the music drive isn't mounted.
+    if !root.is_dir() {
+        return Err(format!("MUSIC_FOLDER `{}` is not a directory", root.display()).into());
+    }
+
+    let mut known = get_music_track_stamps()?;
+    let mut changed = Vec::new();
+    let mut collections = Vec::new();
+    let mut summary = ScanSummary::default();
+
+    for entry in WalkDir::new(root).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(root) else { continue };
+        if is_playlist_file(path) {
+            match std::fs::read(path) {
+                Ok(contents) => collections.push((
+                    relative.to_string_lossy().into_owned(),
+                    parse_playlist(&String::from_utf8_lossy(&contents), relative, root),
+                )),
+                Err(e) => tracing::warn!("Skipping {}: {}", path.display(), e),
+            }
+            continue;
+        }
+        if !is_audio_file(path) {
+            continue;
+        }
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        let size = metadata.len() as i64;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        summary.total += 1;
+        match known.remove(relative.to_string_lossy().as_ref()) {
+            Some(stamp) if stamp == (size, mtime) => continue,
+            Some(_) => summary.updated += 1,
+            None => summary.added += 1,
+        }
+        changed.push(read_track(path, relative, size, mtime));
+    }
+
+    let removed: Vec<String> = known.into_keys().collect();
+    summary.removed = removed.len();
+
+    save_music_tracks(&changed, &removed)?;
+    summary.playlists = collections.len();
+    save_music_collections(&collections)?;
+    Ok(summary)
+}
+
+/// Song paths listed in an .m3u/.m3u8 file at `playlist` (relative to MUSIC_FOLDER), made
+/// relative to MUSIC_FOLDER. Comments, URLs and entries outside the library are skipped.
+fn parse_playlist(contents: &str, playlist: &Path, root: &Path) -> Vec<String> {
+    let dir = playlist.parent().unwrap_or(Path::new(""));
+    contents
+        .lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.contains("://"))
+        .filter_map(|line| {
+            let entry = PathBuf::from(line.replace('\\', "/"));
+            let relative = match entry.strip_prefix(root) {
+                Ok(inside) => inside.to_path_buf(),
+                Err(_) if entry.is_absolute() => return None,
+                Err(_) => dir.join(entry),
+            };
+            normalize_relative(&relative)
+        })
+        .collect()
+}
+
+/// Resolves `.` and `..` in a relative path without touching the disk; `None` if it leaves the root.
+fn normalize_relative(path: &Path) -> Option<String> {
+    let mut parts: Vec<&std::ffi::OsStr> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts.iter().collect::<PathBuf>().to_string_lossy().into_owned())
+}
+
+/// Measures tracks without ReplayGain/R128 tags with ffmpeg's EBU R128 filter and stores the gain
+/// needed to reach [`TARGET_LUFS`]. Stops early (to retry on the next scan) if ffmpeg can't run.
+fn analyze_loudness(root: &Path) -> Result<usize, Error> {
+    let mut analyzed = 0;
+    for (id, relative) in get_tracks_without_loudness()? {
+        match measure_loudness(&root.join(&relative)) {
+            Ok(lufs) => {
+                set_music_track_gain(id, lufs.map(|lufs| TARGET_LUFS - lufs))?;
+                analyzed += 1;
+            }
+            Err(e) => {
+                tracing::warn!("Skipping loudness analysis: {}", e);
+                break;
+            }
+        }
+    }
+    Ok(analyzed)
+}
+
+/// Integrated loudness of a file in LUFS, or `None` if ffmpeg couldn't measure it.
+/// Errors only when ffmpeg itself can't be started.
+fn measure_loudness(path: &Path) -> Result<Option<f64>, Error> {
+    let output = std::process::Command::new(ffmpeg_bin())
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args(["-vn", "-af", "ebur128", "-f", "null", "-"])
+        .output()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    if !output.status.success() {
+        tracing::debug!("Loudness analysis of {} failed: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim());
+        return Ok(None);
+    }
+
+    // The summary at the end of the log has a line like "    I:         -14.3 LUFS".
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(stderr
+        .lines()
+        .rev()
+        .filter_map(|line| line.trim().strip_prefix("I:"))
+        .find_map(|value| value.trim().trim_end_matches("LUFS").trim().parse::<f64>().ok()))
+}
+
+fn read_track(path: &Path, relative: &Path, size: i64, mtime: i64) -> MusicTrackRow {
+    let folder = relative
+        .parent()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let series = if folder.is_empty() {
+        String::new()
+    } else {
+        relative
+            .components()
+            .next()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let file_stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| relative.to_string_lossy().into_owned());
+
+    let mut track = MusicTrackRow {
+        id: 0,
+        path: relative.to_string_lossy().into_owned(),
+        folder,
+        series,
+        title: file_stem,
+        duration_secs: None,
+        size,
+        mtime,
+        artist: None,
+        album: None,
+        track_number: None,
+        gain_db: None,
+    };
+
+    let file = match lofty::read_from_path(path) {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::debug!("Couldn't read tags of {}: {}", path.display(), e);
+            return track;
+        }
+    };
+
+    track.duration_secs = Some(file.properties().duration().as_secs() as i64);
+    if let Some(tag) = file.primary_tag().or_else(|| file.first_tag()) {
+        if let Some(title) = non_empty(tag.title()) {
+            track.title = title;
+        }
+        track.artist = non_empty(tag.artist());
+        track.album = non_empty(tag.album());
+        track.track_number = tag.track().map(i64::from);
+        track.gain_db = tagged_gain(tag);
+    }
+    track
+}
+
+/// Track gain from ReplayGain tags ("-6.5 dB"), or from an Opus R128 tag (Q7.8 fixed point
+/// relative to -23 LUFS, converted to the ReplayGain reference).
+fn tagged_gain(tag: &lofty::tag::Tag) -> Option<f64> {
+    let replaygain = tag.get_string(&ItemKey::ReplayGainTrackGain).and_then(|value| {
+        let value = value.trim();
+        let value = value.strip_suffix("dB").or_else(|| value.strip_suffix("db")).unwrap_or(value);
+        value.trim().parse::<f64>().ok()
+    });
+    replaygain.or_else(|| {
+        tag.get_string(&ItemKey::Unknown("R128_TRACK_GAIN".to_string()))
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .map(|q| q / 256.0 + (TARGET_LUFS + 23.0))
+    })
+}
+
+fn non_empty(value: Option<std::borrow::Cow<'_, str>>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn read_cover_art(path: &Path) -> Option<(Vec<u8>, &'static str)> {
+    let file = lofty::read_from_path(path).ok()?;
+    let pictures: Vec<_> = file.tags().iter().flat_map(|tag| tag.pictures()).collect();
+    let picture = pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())?;
+
+    let ext = match picture.mime_type() {
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Gif) => "gif",
+        Some(MimeType::Jpeg) | None => "jpg",
+        Some(_) => return None,
+    };
+    Some((picture.data().to_vec(), ext))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lofty::tag::{ItemValue, Tag, TagItem, TagType};
+
+    #[test]
+    fn normalize_relative_resolves_dots_inside_the_root() {
+        let cases = [
+            ("Series/song.mp3", Some("Series/song.mp3")),
+            ("Series/./Entry/../song.mp3", Some("Series/song.mp3")),
+            ("./song.mp3", Some("song.mp3")),
+            ("../song.mp3", None),
+            ("Series/../../song.mp3", None),
+            ("/etc/passwd", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(normalize_relative(Path::new(path)).as_deref(), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn parse_playlist_keeps_songs_inside_the_library() {
+        let contents = "\u{feff}#EXTM3U\n\
+            #EXTINF:123,Some Song\n\
+            one.mp3\n\
+            Sub\\two.mp3\n\
+            ../Other/three.mp3\n\
+            /music/Other/four.mp3\n\
+            /elsewhere/five.mp3\n\
+            ../../six.mp3\n\
+            https://example.com/seven.mp3\n\
+            \n";
+        let songs = parse_playlist(contents, Path::new("Series/list.m3u"), Path::new("/music"));
+        assert_eq!(songs, vec!["Series/one.mp3", "Series/Sub/two.mp3", "Other/three.mp3", "Other/four.mp3"]);
+    }
+
+    fn tag_with(items: &[(ItemKey, &str)]) -> Tag {
+        let mut tag = Tag::new(TagType::VorbisComments);
+        for (key, value) in items {
+            tag.insert_unchecked(TagItem::new(key.clone(), ItemValue::Text(value.to_string())));
+        }
+        tag
+    }
+
+    #[test]
+    fn tagged_gain_reads_replaygain_and_r128() {
+        let r128 = || ItemKey::Unknown("R128_TRACK_GAIN".to_string());
+        let cases = [
+            (vec![(ItemKey::ReplayGainTrackGain, "-6.5 dB")], Some(-6.5)),
+            (vec![(ItemKey::ReplayGainTrackGain, "+2.25db")], Some(2.25)),
+            (vec![(ItemKey::ReplayGainTrackGain, " 1.5 ")], Some(1.5)),
+            // Q7.8 relative to -23 LUFS: -5 dB there is 0 dB at the -18 LUFS reference.
+            (vec![(r128(), "-1280")], Some(0.0)),
+            (vec![(r128(), "256")], Some(6.0)),
+            (vec![(ItemKey::ReplayGainTrackGain, "-3 dB"), (r128(), "256")], Some(-3.0)),
+            (vec![(ItemKey::ReplayGainTrackGain, "loud")], None),
+            (vec![], None),
+        ];
+        for (items, expected) in cases {
+            assert_eq!(tagged_gain(&tag_with(&items)), expected, "{:?}", items);
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod commands;
+pub mod library;
 pub mod models;
+pub mod quiz;
+pub mod radio;
 pub mod utils;
\ No newline at end of file
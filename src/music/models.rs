@@ -1,62 +1,106 @@
-#[derive(Debug, Clone, poise::ChoiceParameter)]
-pub enum Game {
-    #[name = "Final Fantasy"]
-    FF,
-    #[name = "Kingdom Hearts"]
-    KH,
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use poise::serenity_prelude::{GuildId, UserId};
+use crate::music::library::track_path;
+use crate::shared::db::MusicTrackRow;
+
+#[derive(Debug)]
+pub struct QueuedSong {
+    pub track: MusicTrackRow,
+    pub path: PathBuf,
+    pub guild_id: GuildId,
+    pub requested_by: UserId,
+    /// The command or button that queued the song, recorded in the play history.
+    pub source: String,
+    pub skip_votes: Mutex<HashSet<UserId>>,
+    /// Play history row, written once the song starts playing.
+    pub history_id: Mutex<Option<i64>>,
 }
 
-impl Game {
-    pub fn folder_name(&self) -> &'static str {
-        match self {
-            Game::FF => "Final Fantasy/XIV",
-            Game::KH => "Kingdom Hearts",
+impl QueuedSong {
+    pub fn new(track: MusicTrackRow, guild_id: GuildId, requested_by: UserId, source: &str) -> Self {
+        Self {
+            path: track_path(&track),
+            track,
+            guild_id,
+            requested_by,
+            source: source.to_string(),
+            skip_votes: Mutex::new(HashSet::new()),
+            history_id: Mutex::new(None),
         }
     }
 }
 
-#[derive(Debug, Clone, poise::ChoiceParameter)]
-pub enum FinalFantasyExpansion {
-    #[name = "XIV Online (1.0)"] XIV,
-    #[name = "A Realm Reborn (ARR)"] ARR,
-    #[name = "Heavensward (HW)"] HW,
-    #[name = "Stormblood (SB)"] SB,
-    #[name = "Shadowbringers (ShB)"] ShB,
-    #[name = "Endwalker (EW)"] EW,
-    #[name = "Dawntrail (DT)"] DT,
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum SongAction {
+    #[name = "Send the file"] Attach,
+    #[name = "Play it in voice now"] Play,
+    #[name = "Add it to the voice queue"] Queue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum SelectionMode {
+    #[name = "Any song (repeats allowed)"] Uniform,
+    #[name = "Skip recently played songs"] AvoidRecent,
+    #[name = "Favour songs not played in a while"] Weighted,
 }
 
-impl FinalFantasyExpansion {
-    pub fn folder_name(&self) -> &'static str {
+impl SelectionMode {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Self::XIV => "XIV",
-            Self::ARR => "A Realm Reborn",
-            Self::HW => "Heavensward",
-            Self::SB => "Stormblood",
-            Self::ShB => "Shadowbringers",
-            Self::EW => "Endwalker",
-            Self::DT => "Dawntrail",
+            Self::Uniform => "uniform",
+            Self::AvoidRecent => "avoid_recent",
+            Self::Weighted => "weighted",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "uniform" => Self::Uniform,
+            "weighted" => Self::Weighted,
+            _ => Self::AvoidRecent,
         }
     }
 }
 
-#[derive(Debug, Clone, poise::ChoiceParameter)]
-pub enum KingdomHeartsTitle {
-    #[name = "I"] I,
-    #[name = "II"] II,
-    #[name = "III"] III,
-    #[name = "Dream Drop Distance"] DDD,
-    #[name = "Birth by Sleep + 358-2 Days"] BBSDAYS,
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum QuizGuess {
+    #[name = "The series (e.g. Kingdom Hearts)"] Series,
+    #[name = "The entry within the series (e.g. XIV › Endwalker)"] Entry,
+    #[name = "The song title"] Title,
 }
 
-impl KingdomHeartsTitle {
-    pub fn folder_name(&self) -> &'static str {
+impl QuizGuess {
+    pub fn noun(&self) -> &'static str {
         match self {
-            Self::I => "Kingdom Hearts",
-            Self::II => "Kingdom Hearts II",
-            Self::III => "Kingdom Hearts III, II.8, Unchained χ & Union χ [Cross]",
-            Self::DDD => "Dream Drop Distance",
-            Self::BBSDAYS => "Birth by Sleep & 358-2 Days",
+            Self::Series => "series",
+            Self::Entry => "entry",
+            Self::Title => "song",
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum QuizMedium {
+    #[name = "Play clips in my voice channel"] Voice,
+    #[name = "Attach clips to the messages"] Attachment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum StatsPeriod {
+    #[name = "The last 7 days"] Week,
+    #[name = "The last 30 days"] Month,
+    #[name = "All time"] AllTime,
+}
+
+impl StatsPeriod {
+    pub fn since(&self) -> Option<String> {
+        let days = match self {
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::AllTime => return None,
+        };
+        Some((chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339())
+    }
+}
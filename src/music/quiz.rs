@@ -0,0 +1,369 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use poise::futures_util::StreamExt;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::{CreateActionRow, CreateAttachment, CreateButton, CreateEmbed};
+use rand::Rng;
+use rand::seq::{IndexedRandom, SliceRandom};
+use songbird::input;
+use songbird::Call;
+use strsim::jaro_winkler;
+use tokio::sync::Mutex;
+use crate::music::library::track_path;
+use crate::music::models::{QuizGuess, QuizMedium};
+use crate::music::utils::{normalize_for_search, song_components, track_embed, track_volume, trim_clip, with_cover_art};
+use crate::shared::db::{add_quiz_point, MusicTrackRow};
+use crate::shared::types::{Context, Data, Error};
+use crate::voice::utils::{cleanup_file, guild_volume};
+
+pub const CLIP_SECS: u64 = 15;
+pub const ROUND_SECS: u64 = 30;
+const CHAT_MATCH_THRESHOLD: f64 = 0.92;
+
+pub struct QuizSettings {
+    pub pool: Vec<MusicTrackRow>,
+    pub rounds: u8,
+    pub guess: QuizGuess,
+    pub medium: QuizMedium,
+    pub call: Option<Arc<Mutex<Call>>>,
+}
+
+/// A quiz in progress. `/music quiz stop` only raises `stop`; the run itself frees its channel.
+#[derive(Debug)]
+pub struct QuizRun {
+    pub guild_id: serenity::GuildId,
+    pub in_voice: bool,
+    pub stop: AtomicBool,
+}
+
+/// A channel's quiz slot, freed when dropped so every way out of a quiz releases it.
+pub struct QuizSlot<'a> {
+    data: &'a Data,
+    channel: serenity::ChannelId,
+    pub run: Arc<QuizRun>,
+}
+
+impl<'a> QuizSlot<'a> {
+    pub fn claim(data: &'a Data, channel: serenity::ChannelId, guild_id: serenity::GuildId, in_voice: bool) -> Option<Self> {
+        let mut quizzes = data.quiz_channels.lock().unwrap_or_else(|e| e.into_inner());
+        if quizzes.contains_key(&channel) {
+            return None;
+        }
+        let run = Arc::new(QuizRun { guild_id, in_voice, stop: AtomicBool::new(false) });
+        quizzes.insert(channel, run.clone());
+        Some(Self { data, channel, run })
+    }
+}
+
+impl Drop for QuizSlot<'_> {
+    fn drop(&mut self) {
+        let mut quizzes = self.data.quiz_channels.lock().unwrap_or_else(|e| e.into_inner());
+        if quizzes.get(&self.channel).is_some_and(|run| Arc::ptr_eq(run, &self.run)) {
+            quizzes.remove(&self.channel);
+        }
+    }
+}
+
+/// Whether a quiz is playing clips in the guild's voice call, which music mustn't play over.
+pub fn voice_quiz_running(data: &Data, guild_id: serenity::GuildId) -> bool {
+    data.quiz_channels
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .any(|run| run.in_voice && run.guild_id == guild_id)
+}
+
+struct QuizRound {
+    track: MusicTrackRow,
+    options: Vec<String>,
+    correct: usize,
+}
+
+/// The answer players have to give for a track, or `None` if the track can't be asked about
+/// that way (e.g. a song directly in a series folder has no entry).
+pub fn quiz_answer(track: &MusicTrackRow, guess: QuizGuess) -> Option<String> {
+    match guess {
+        QuizGuess::Series => Some(track.series.clone()).filter(|s| !s.is_empty()),
+        QuizGuess::Entry => track
+            .folder
+            .strip_prefix(&track.series)
+            .map(|entry| entry.trim_start_matches('/').replace('/', " › "))
+            .filter(|entry| !entry.is_empty()),
+        QuizGuess::Title => Some(track.title.clone()),
+    }
+}
+
+pub fn distinct_answers(pool: &[MusicTrackRow], guess: QuizGuess) -> usize {
+    pool.iter().filter_map(|t| quiz_answer(t, guess)).collect::<HashSet<_>>().len()
+}
+
+/// Picks a random answer first and then one of its songs, so big folders don't crowd out the
+/// rest, and adds up to three other answers from the pool as decoys.
+fn build_round(pool: &[MusicTrackRow], guess: QuizGuess, used: &HashSet<i64>) -> Option<QuizRound> {
+    let mut by_answer: HashMap<String, Vec<&MusicTrackRow>> = HashMap::new();
+    for track in pool {
+        if let Some(answer) = quiz_answer(track, guess) {
+            by_answer.entry(answer).or_default().push(track);
+        }
+    }
+
+    let mut rng = rand::rng();
+    let fresh: Vec<&String> = by_answer
+        .iter()
+        .filter(|(_, tracks)| tracks.iter().any(|t| !used.contains(&t.id)))
+        .map(|(answer, _)| answer)
+        .collect();
+    let answer = (*fresh.choose(&mut rng)?).clone();
+    let candidates: Vec<&&MusicTrackRow> = by_answer[&answer].iter().filter(|t| !used.contains(&t.id)).collect();
+    let track = (**candidates.choose(&mut rng)?).clone();
+
+    let mut decoys: Vec<&String> = by_answer.keys().filter(|a| **a != answer).collect();
+    decoys.shuffle(&mut rng);
+    if decoys.is_empty() {
+        return None;
+    }
+
+    let mut options: Vec<String> = decoys.into_iter().take(3).cloned().collect();
+    options.push(answer.clone());
+    options.shuffle(&mut rng);
+    let correct = options.iter().position(|o| *o == answer)?;
+
+    Some(QuizRound { track, options, correct })
+}
+
+/// Where to start a clip: somewhere past the intro, leaving room for the whole clip.
+fn clip_start(duration_secs: Option<i64>) -> u64 {
+    let Some(duration) = duration_secs.map(|d| d.max(0) as u64) else { return 0 };
+    let earliest = duration / 10;
+    let latest = duration.saturating_sub(CLIP_SECS + 5);
+    if latest > earliest { rand::rng().random_range(earliest..latest) } else { 0 }
+}
+
+fn option_named_in(text: &str, options: &[String]) -> Option<usize> {
+    let text = normalize_for_search(text).split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    options
+        .iter()
+        .enumerate()
+        .map(|(idx, option)| {
+            let option = normalize_for_search(option).split_whitespace().collect::<Vec<_>>().join(" ");
+            (idx, jaro_winkler(&text, &option))
+        })
+        .filter(|(_, score)| *score >= CHAT_MATCH_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(idx, _)| idx)
+}
+
+fn option_buttons(round_id: &str, round: &QuizRound, revealed: bool) -> Vec<CreateActionRow> {
+    let buttons = round
+        .options
+        .iter()
+        .enumerate()
+        .map(|(idx, option)| {
+            // Discord rejects button labels longer than 80 characters.
+            let label = if option.chars().count() > 80 {
+                option.chars().take(79).collect::<String>() + "…"
+            } else {
+                option.clone()
+            };
+            let style = if revealed && idx == round.correct {
+                serenity::ButtonStyle::Success
+            } else {
+                serenity::ButtonStyle::Secondary
+            };
+            CreateButton::new(format!("{}:{}", round_id, idx))
+                .label(label)
+                .style(style)
+                .disabled(revealed)
+        })
+        .collect();
+    vec![CreateActionRow::Buttons(buttons)]
+}
+
+pub async fn run_quiz(ctx: Context<'_>, settings: QuizSettings, run: &QuizRun) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().map(|g| g.get() as i64).unwrap_or_default();
+    let mut used = HashSet::new();
+    let mut scores: HashMap<serenity::UserId, u32> = HashMap::new();
+    let mut played = 0;
+
+    for number in 1..=settings.rounds {
+        if run.stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let Some(round) = build_round(&settings.pool, settings.guess, &used) else { break };
+        used.insert(round.track.id);
+
+        let path = track_path(&round.track);
+        let start = clip_start(round.track.duration_secs);
+        let round_id = format!("quiz:{}:{}", ctx.id(), number);
+
+        let embed = CreateEmbed::new()
+            .title(format!("🎧 Music Quiz — Round {}/{}", number, settings.rounds))
+            .description(format!(
+                "Which {} is this? Press a button or type your answer in chat. You have {} seconds, one guess each.",
+                settings.guess.noun(),
+                ROUND_SECS
+            ))
+            .color(serenity::Colour::PURPLE);
+        let mut reply = poise::CreateReply::default()
+            .embed(embed.clone())
+            .components(option_buttons(&round_id, &round, false));
+
+        let mut clip_handle = None;
+        match (&settings.medium, &settings.call) {
+            (QuizMedium::Voice, Some(call_lock)) => {
+                let handle = call_lock.lock().await.play_input(input::File::new(path.clone()).into());
+                let _ = handle.set_volume(track_volume(&round.track, guild_volume(ctx.guild_id().unwrap_or_default())));
+                let _ = handle.pause();
+                let _ = handle.seek_async(Duration::from_secs(start)).await;
+                let _ = handle.play();
+                clip_handle = Some(handle);
+            }
+            _ => {
+                let clip = match trim_clip(&path, start, CLIP_SECS).await {
+                    Ok(clip) => clip,
+                    Err(e) => {
+                        tracing::error!("Failed to cut quiz clip from {}: {}", path.display(), e);
+                        ctx.say("Couldn't cut a clip for the quiz (is ffmpeg installed?). Stopping the quiz.").await?;
+                        break;
+                    }
+                };
+                let content = tokio::fs::read(&clip).await;
+                cleanup_file(clip).await;
+                reply = reply.attachment(CreateAttachment::bytes(content?, "quiz_clip.ogg"));
+            }
+        }
+
+        let handle = ctx.send(reply).await?;
+        let message_id = handle.message().await?.id;
+        played += 1;
+
+        if let Some(clip) = clip_handle.clone() {
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(CLIP_SECS)).await;
+                let _ = clip.stop();
+            });
+        }
+
+        let winner = collect_answer(ctx, message_id, &round).await;
+        if let Some(clip) = clip_handle {
+            let _ = clip.stop();
+        }
+
+        let _ = handle
+            .edit(ctx, poise::CreateReply::default().embed(embed).components(option_buttons(&round_id, &round, true)))
+            .await;
+
+        let heading = format!("✅ It was: {}", round.options[round.correct]);
+        let mut reveal = track_embed(&round.track, &heading);
+        match winner {
+            Some(user_id) => {
+                *scores.entry(user_id).or_default() += 1;
+                if let Err(e) = add_quiz_point(guild_id, user_id.get() as i64) {
+                    tracing::warn!("Failed to save quiz point: {}", e);
+                }
+                reveal = reveal.field("Point to", format!("<@{}>", user_id.get()), true);
+            }
+            None => reveal = reveal.field("Point to", "Nobody got it this time.", true),
+        }
+        let (reveal, cover) = with_cover_art(reveal, &path).await;
+        let mut reply = poise::CreateReply::default()
+            .embed(reveal)
+            .components(song_components(round.track.id, false));
+        if let Some(cover) = cover {
+            reply = reply.attachment(cover);
+        }
+        ctx.send(reply).await?;
+
+        if number < settings.rounds {
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+    }
+
+    let mut standings: Vec<(serenity::UserId, u32)> = scores.into_iter().collect();
+    standings.sort_by_key(|(_, points)| std::cmp::Reverse(*points));
+    let mut lines: Vec<String> = standings
+        .iter()
+        .enumerate()
+        .map(|(idx, (user_id, points))| format!("{}. <@{}> — {} pts", idx + 1, user_id.get(), points))
+        .collect();
+    if lines.is_empty() {
+        lines.push("Nobody scored this time.".to_string());
+    }
+
+    let embed = CreateEmbed::new()
+        .title("🏁 Quiz Over")
+        .description(lines.join("\n"))
+        .footer(serenity::CreateEmbedFooter::new(format!(
+            "{} rounds played · see /music quiz leaderboard for all-time scores",
+            played
+        )))
+        .color(serenity::Colour::PURPLE);
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Waits for the first correct answer to a round, from a button press or a chat message
+/// naming one of the options. A wrong guess locks the player out for the rest of the round.
+async fn collect_answer(ctx: Context<'_>, message_id: serenity::MessageId, round: &QuizRound) -> Option<serenity::UserId> {
+    let timeout = Duration::from_secs(ROUND_SECS);
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut presses = serenity::ComponentInteractionCollector::new(ctx.serenity_context())
+        .message_id(message_id)
+        .timeout(timeout)
+        .stream();
+    let mut messages = serenity::MessageCollector::new(ctx.serenity_context())
+        .channel_id(ctx.channel_id())
+        .filter(|m| !m.author.bot)
+        .timeout(timeout)
+        .stream();
+    let mut locked_out = HashSet::new();
+
+    loop {
+        tokio::select! {
+            Some(press) = presses.next() => {
+                let choice = press.data.custom_id.rsplit(':').next().and_then(|idx| idx.parse::<usize>().ok());
+                let response = if locked_out.contains(&press.user.id) {
+                    Some("You already guessed this round.")
+                } else if choice == Some(round.correct) {
+                    None
+                } else {
+                    locked_out.insert(press.user.id);
+                    Some("❌ Not quite! Wait for the next round.")
+                };
+
+                let builder = match response {
+                    Some(content) => serenity::CreateInteractionResponse::Message(
+                        serenity::CreateInteractionResponseMessage::new().content(content).ephemeral(true),
+                    ),
+                    None => serenity::CreateInteractionResponse::Acknowledge,
+                };
+                let _ = press.create_response(ctx, builder).await;
+                if response.is_none() {
+                    return Some(press.user.id);
+                }
+            }
+            Some(message) = messages.next() => {
+                if locked_out.contains(&message.author.id) {
+                    continue;
+                }
+                match option_named_in(&message.content, &round.options) {
+                    Some(idx) if idx == round.correct => {
+                        let _ = message.react(ctx, '✅').await;
+                        return Some(message.author.id);
+                    }
+                    Some(_) => {
+                        locked_out.insert(message.author.id);
+                        let _ = message.react(ctx, '❌').await;
+                    }
+                    None => {}
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => return None,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use poise::serenity_prelude as serenity;
+use rand::seq::IndexedRandom;
+use crate::music::models::QueuedSong;
+use crate::music::utils::{announce_now_playing, enqueue_song};
+use crate::shared::db::{get_music_settings, get_music_tracks_in_folder, get_recent_music_track_ids, MusicTrackRow};
+use crate::shared::types::Error;
+use crate::voice::utils::{guild_call, voice_channel_listeners};
+
+const POLL_SECS: u64 = 5;
+/// Songs kept queued behind the current one so the next track is ready in time.
+const QUEUE_AHEAD: usize = 1;
+
+pub struct RadioStation {
+    pub folder: String,
+    pub guild_id: serenity::GuildId,
+    pub channel: serenity::ChannelId,
+    pub requested_by: serenity::UserId,
+}
+
+/// Keeps the guild's queue filled with random songs from the station's folder until the bot
+/// leaves the voice channel, the task is aborted, or nobody has listened for the configured time.
+pub async fn run_radio(ctx: serenity::Context, station: RadioStation) {
+    let guild = station.guild_id.get() as i64;
+    let settings = match get_music_settings(guild) {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::error!("Failed to load music settings for the radio: {}", e);
+            return;
+        }
+    };
+    let idle_limit = Duration::from_secs(settings.radio_idle_minutes.max(1) as u64 * 60);
+
+    // Songs played recently in the server count as already heard.
+    let mut played: HashSet<i64> = get_recent_music_track_ids(guild, settings.history_size)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let mut empty_since: Option<Instant> = None;
+    let mut interval = tokio::time::interval(Duration::from_secs(POLL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let Some(call_lock) = guild_call(&ctx, station.guild_id).await else { break };
+        let (queued, voice_channel) = {
+            let call = call_lock.lock().await;
+            (call.queue().len(), call.current_channel())
+        };
+        let Some(voice_channel) = voice_channel else { break };
+
+        let voice_channel = serenity::ChannelId::new(voice_channel.0.get());
+        if voice_channel_listeners(&ctx, station.guild_id, voice_channel).is_empty() {
+            if empty_since.get_or_insert_with(Instant::now).elapsed() >= idle_limit {
+                call_lock.lock().await.queue().stop();
+                if let Some(manager) = songbird::get(&ctx).await
+                    && let Err(e) = manager.leave(station.guild_id).await
+                {
+                    tracing::error!("Songbird leave error: {}", e);
+                }
+                let msg = format!(
+                    "📻 Radio stopped: nobody has been listening for {} minutes.",
+                    settings.radio_idle_minutes
+                );
+                let _ = station.channel.say(&ctx.http, msg).await;
+                break;
+            }
+        } else {
+            empty_since = None;
+        }
+
+        if queued > QUEUE_AHEAD {
+            continue;
+        }
+
+        let track = match next_song(&station.folder, &mut played) {
+            Ok(Some(track)) => track,
+            Ok(None) => {
+                let msg = format!("📻 Radio stopped: there are no songs left in `{}`.", station.folder);
+                let _ = station.channel.say(&ctx.http, msg).await;
+                break;
+            }
+            Err(e) => {
+                tracing::error!("Radio failed to pick a song: {}", e);
+                continue;
+            }
+        };
+
+        let song = QueuedSong::new(track, station.guild_id, station.requested_by, "music radio");
+        // Queued songs announce themselves when they start; the first one is announced here.
+        if queued == 0 {
+            announce_now_playing(&ctx.http, station.channel, &song).await;
+        }
+        enqueue_song(&call_lock, song, ctx.http.clone(), station.channel).await;
+    }
+}
+
+/// Picks a song from `folder` that isn't in `played`, starting over once every song has played.
+fn next_song(folder: &str, played: &mut HashSet<i64>) -> Result<Option<MusicTrackRow>, Error> {
+    let tracks = get_music_tracks_in_folder(folder)?;
+    let mut fresh: Vec<&MusicTrackRow> = tracks.iter().filter(|track| !played.contains(&track.id)).collect();
+    if fresh.is_empty() {
+        played.clear();
+        fresh = tracks.iter().collect();
+    }
+
+    let Some(track) = fresh.choose(&mut rand::rng()).map(|track| (*track).clone()) else {
+        return Ok(None);
+    };
+    played.insert(track.id);
+    Ok(Some(track))
+}
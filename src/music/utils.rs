@@ -1,31 +1,607 @@
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use rand::prelude::IndexedRandom;
-use walkdir::WalkDir;
-use crate::shared::types::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::{CreateAttachment, CreateEmbed};
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::Distribution;
+use songbird::events::{Event, EventContext, EventData, EventHandler, TrackEvent};
+use songbird::input;
+use songbird::tracks::Track;
+use songbird::Call;
+use tokio::sync::Mutex;
+use tokio::task;
+use crate::music::models::{QueuedSong, SelectionMode};
+use crate::music::library::{indexed_tracks, music_root, read_cover_art, request_scan};
+use crate::shared::db::{
+    get_music_settings, get_music_track, get_music_track_last_plays, get_music_track_ratings,
+    get_recent_music_track_ids, log_music_play, random_music_track, random_music_track_avoiding_recent,
+    set_music_play_secs, MusicStatsRow, MusicTrackRow,
+};
+use crate::shared::types::{Context, Error};
+use crate::voice::utils::{cleanup_file, guild_volume};
 
-pub async fn get_random_song(dir_path: impl AsRef<Path>) -> Result<Option<PathBuf>, Error> {
-    let mut songs = Vec::new();
-    let dir_path = dir_path.as_ref();
-
-    if !dir_path.is_dir() {
-        return Ok(None);
+/// Picks a random indexed song from `folder` (relative to MUSIC_FOLDER, empty for everything)
+/// following the guild's selection mode. Folders or playlist files that exist on disk but have no
+/// indexed songs start a background rescan, so newly added ones show up shortly.
+pub fn get_random_song(folder: &str, guild_id: Option<serenity::GuildId>) -> Result<Option<MusicTrackRow>, Error> {
+    let track = select_song(folder, None, guild_id.map(|g| g.get() as i64))?;
+    if track.is_none() && music_root().join(folder).exists() {
+        request_scan();
     }
+    Ok(track)
+}
+
+/// Longest gap (in hours) that still increases a song's weight in weighted selection.
+const WEIGHT_CAP_HOURS: f64 = 24.0 * 7.0;
+
+pub fn select_song(folder: &str, max_size: Option<i64>, guild_id: Option<i64>) -> Result<Option<MusicTrackRow>, Error> {
+    let Some(guild_id) = guild_id else {
+        return random_music_track(folder, max_size);
+    };
+    let settings = get_music_settings(guild_id)?;
+    let mode = SelectionMode::from_db(&settings.selection_mode);
 
-    for entry in WalkDir::new(dir_path).into_iter().filter_map(Result::ok) {
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(ext) = path.extension() {
-                if ext == "mp3" || ext == "ogg" || ext == "wav" {
-                    songs.push(path.to_path_buf());
+    match mode {
+        SelectionMode::Uniform if !settings.prefer_rated => random_music_track(folder, max_size),
+        SelectionMode::AvoidRecent if !settings.prefer_rated => {
+            match random_music_track_avoiding_recent(folder, max_size, guild_id, settings.history_size)? {
+                Some(track) => Ok(Some(track)),
+                // Everything in the folder was played recently.
+                None => random_music_track(folder, max_size),
+            }
+        }
+        _ => {
+            let mut candidates = get_music_track_last_plays(folder, max_size, guild_id)?;
+            if mode == SelectionMode::AvoidRecent {
+                let recent: HashSet<i64> = get_recent_music_track_ids(guild_id, settings.history_size)?.into_iter().collect();
+                if candidates.iter().any(|(id, _)| !recent.contains(id)) {
+                    candidates.retain(|(id, _)| !recent.contains(id));
                 }
             }
+            if candidates.is_empty() {
+                return Ok(None);
+            }
+
+            let ratings = if settings.prefer_rated { get_music_track_ratings(guild_id)? } else { HashMap::new() };
+            let now = Utc::now();
+            let weights: Vec<f64> = candidates
+                .iter()
+                .map(|(id, last_played)| {
+                    let base = if mode == SelectionMode::Weighted {
+                        let hours = last_played
+                            .as_deref()
+                            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+                            .map(|ts| (now - ts.with_timezone(&Utc)).num_minutes() as f64 / 60.0)
+                            .unwrap_or(WEIGHT_CAP_HOURS);
+                        hours.clamp(0.0, WEIGHT_CAP_HOURS) + 1.0
+                    } else {
+                        1.0
+                    };
+                    // Unrated songs count as three stars; each star above or below doubles or halves the odds.
+                    let rating = ratings.get(id).copied().unwrap_or(3.0);
+                    base * 2f64.powf(rating - 3.0)
+                })
+                .collect();
+
+            let index = WeightedIndex::new(&weights)?.sample(&mut rand::rng());
+            get_music_track(candidates[index].0)
         }
     }
+}
+
+pub fn upload_limit(tier: Option<serenity::PremiumTier>) -> u64 {
+    const MIB: u64 = 1024 * 1024;
+    match tier {
+        Some(serenity::PremiumTier::Tier2) => 50 * MIB,
+        Some(serenity::PremiumTier::Tier3) => 100 * MIB,
+        _ => 10 * MIB,
+    }
+}
+
+pub fn ffmpeg_bin() -> String {
+    std::env::var("FFMPEG_BIN")
+        .ok()
+        .filter(|bin| !bin.trim().is_empty())
+        .unwrap_or_else(|| "ffmpeg".to_string())
+}
+
+/// A fresh path in the temp directory. The counter keeps concurrent sends from sharing a file.
+fn temp_file(prefix: &str, extension: &str) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("{}_{}_{}.{}", prefix, std::process::id(), n, extension))
+}
+
+/// Lowest Opus bitrate worth sending; anything below sounds too rough.
+const MIN_TRANSCODE_KBPS: u64 = 24;
+
+/// Re-encodes a song to Opus/OGG with ffmpeg at a bitrate chosen to fit `max_bytes`.
+/// Returns the temporary output file, or `None` if the song is too long to fit at a usable bitrate.
+pub async fn transcode_to_fit(path: &Path, duration_secs: Option<i64>, max_bytes: u64) -> Result<Option<PathBuf>, Error> {
+    // Leave some headroom for the container overhead.
+    let kbps = match duration_secs.filter(|secs| *secs > 0) {
+        Some(secs) => (max_bytes * 8 * 95 / 100 / secs as u64 / 1000).min(128),
+        None => 96,
+    };
+    if kbps < MIN_TRANSCODE_KBPS {
+        return Ok(None);
+    }
+
+    let out = temp_file("shaggy_music", "ogg");
+
+    let input = path.to_path_buf();
+    let output = out.clone();
+    task::spawn_blocking(move || -> Result<(), Error> {
+        let result = std::process::Command::new(ffmpeg_bin())
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(&input)
+            .args(["-vn", "-map_metadata", "0", "-c:a", "libopus", "-b:a", &format!("{}k", kbps)])
+            .arg(&output)
+            .output()
+            .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+        if !result.status.success() {
+            return Err(format!("ffmpeg failed: {}", String::from_utf8_lossy(&result.stderr).trim()).into());
+        }
+        Ok(())
+    }).await??;
+
+    if tokio::fs::metadata(&out).await?.len() > max_bytes {
+        cleanup_file(out).await;
+        return Ok(None);
+    }
+    Ok(Some(out))
+}
+
+/// Cuts `secs` seconds starting at `start_secs` out of a song into a temporary Opus/OGG file.
+/// Tags are dropped so the clip doesn't give the song away.
+pub async fn trim_clip(path: &Path, start_secs: u64, secs: u64) -> Result<PathBuf, Error> {
+    let out = temp_file("shaggy_clip", "ogg");
+
+    let input = path.to_path_buf();
+    let output = out.clone();
+    task::spawn_blocking(move || -> Result<(), Error> {
+        let result = std::process::Command::new(ffmpeg_bin())
+            .args(["-y", "-loglevel", "error", "-ss", &start_secs.to_string(), "-t", &secs.to_string(), "-i"])
+            .arg(&input)
+            .args(["-vn", "-map_metadata", "-1", "-c:a", "libopus", "-b:a", "96k"])
+            .arg(&output)
+            .output()
+            .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+        if !result.status.success() {
+            return Err(format!("ffmpeg failed: {}", String::from_utf8_lossy(&result.stderr).trim()).into());
+        }
+        Ok(())
+    }).await??;
+
+    Ok(out)
+}
+
+/// Lowercases and replaces punctuation with spaces so "Footfalls (Endwalker)" matches "footfalls endwalker".
+pub fn normalize_for_search(text: &str) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect()
+}
+
+/// Scores how well `query` matches a track's title, tags and file name; higher is better.
+/// A contiguous match scores above 2.0, otherwise each query word is compared against the
+/// closest word of the track (prefix match or Jaro-Winkler similarity) and averaged.
+pub fn fuzzy_score(query: &str, track: &MusicTrackRow) -> f64 {
+    let query = normalize_for_search(query);
+    let query_words: Vec<&str> = query.split_whitespace().collect();
+    if query_words.is_empty() {
+        return 0.0;
+    }
+
+    let file_stem = Path::new(&track.path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let haystack = normalize_for_search(&format!(
+        "{} {} {} {}",
+        track.title,
+        track.artist.as_deref().unwrap_or_default(),
+        track.album.as_deref().unwrap_or_default(),
+        file_stem
+    ));
+
+    let query_joined = query_words.join(" ");
+    let haystack_words: Vec<&str> = haystack.split_whitespace().collect();
+    if haystack_words.join(" ").contains(&query_joined) {
+        let title_bonus = if normalize_for_search(&track.title).trim_start().starts_with(&query_joined) { 0.5 } else { 0.0 };
+        return 2.0 + title_bonus;
+    }
+
+    let total: f64 = query_words
+        .iter()
+        .map(|word| {
+            haystack_words
+                .iter()
+                .map(|candidate| if candidate.starts_with(word) { 1.0 } else { strsim::jaro_winkler(word, candidate) })
+                .fold(0.0, f64::max)
+        })
+        .sum();
+    total / query_words.len() as f64
+}
+
+const SEARCH_THRESHOLD: f64 = 0.85;
+
+pub fn search_tracks(query: &str, limit: usize) -> Result<Vec<MusicTrackRow>, Error> {
+    let tracks = indexed_tracks()?;
+    let mut scored: Vec<(f64, &MusicTrackRow)> = tracks
+        .iter()
+        .map(|track| (fuzzy_score(query, track), track))
+        .filter(|(score, _)| *score >= SEARCH_THRESHOLD)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.title.cmp(&b.1.title)));
+    Ok(scored.into_iter().take(limit).map(|(_, track)| track.clone()).collect())
+}
+
+pub fn track_embed(track: &MusicTrackRow, heading: &str) -> CreateEmbed {
+    let mut description = format!("**{}**", track.title);
+    if let Some(artist) = &track.artist {
+        description.push_str(&format!("\n{}", artist));
+    }
+    if let Some(album) = &track.album {
+        description.push_str(&format!("\n*{}*", album));
+    }
+
+    let mut embed = CreateEmbed::new()
+        .title(heading)
+        .description(description)
+        .color(serenity::Colour::PURPLE);
+
+    if let Some(number) = track.track_number {
+        embed = embed.field("Track", number.to_string(), true);
+    }
+    if let Some(secs) = track.duration_secs {
+        embed = embed.field("Duration", format_position(Duration::from_secs(secs as u64)), true);
+    }
+    if !track.folder.is_empty() {
+        embed = embed.field("Folder", track.folder.replace('/', " › "), true);
+    }
+    if let Some(file_name) = Path::new(&track.path).file_name() {
+        embed = embed.footer(serenity::CreateEmbedFooter::new(file_name.to_string_lossy()));
+    }
+    embed
+}
+
+pub fn now_playing_embed(song: &QueuedSong) -> CreateEmbed {
+    track_embed(&song.track, "🎶 Now Playing")
+        .field("Requested by", format!("<@{}>", song.requested_by.get()), true)
+}
+
+pub async fn with_cover_art(embed: CreateEmbed, path: &Path) -> (CreateEmbed, Option<CreateAttachment>) {
+    with_cover_art_within(embed, path, u64::MAX).await
+}
+
+/// Like [`with_cover_art`], but leaves the cover out when it is larger than `budget` bytes, so it
+/// doesn't push a message with an attached song over the upload limit.
+pub async fn with_cover_art_within(embed: CreateEmbed, path: &Path, budget: u64) -> (CreateEmbed, Option<CreateAttachment>) {
+    let path = path.to_path_buf();
+    match tokio::task::spawn_blocking(move || read_cover_art(&path)).await {
+        Ok(Some((data, ext))) if data.len() as u64 <= budget => {
+            let name = format!("cover.{}", ext);
+            let embed = embed.thumbnail(format!("attachment://{}", name));
+            (embed, Some(CreateAttachment::bytes(data, name)))
+        }
+        _ => (embed, None),
+    }
+}
+
+pub fn track_volume(track: &MusicTrackRow, guild_volume: f32) -> f32 {
+    let gain = track.gain_db.map(|db| 10f64.powf(db / 20.0)).unwrap_or(1.0);
+    // Don't boost quiet tracks into clipping or bury loud ones entirely.
+    guild_volume * gain.clamp(0.25, 2.0) as f32
+}
+
+pub async fn is_dj(ctx: Context<'_>, dj_role_id: Option<i64>) -> bool {
+    let Some(role_id) = dj_role_id else { return false };
+    ctx.author_member()
+        .await
+        .is_some_and(|member| member.roles.contains(&serenity::RoleId::new(role_id as u64)))
+}
+
+/// Skip votes needed among `listeners` for a share of `percent`, rounded up.
+pub fn votes_needed(listeners: usize, percent: i64) -> usize {
+    (listeners * percent.clamp(1, 100) as usize).div_ceil(100).max(1)
+}
+
+/// Appends a song to the guild's queue and returns its position (0 means it is playing now).
+/// Songs that have to wait get a now-playing announcement in `channel` once they start.
+pub async fn enqueue_song(
+    call_lock: &Arc<Mutex<Call>>,
+    song: QueuedSong,
+    http: Arc<serenity::Http>,
+    channel: serenity::ChannelId,
+) -> usize {
+    let song = Arc::new(song);
+    let track = song_track(&song);
+
+    let mut call = call_lock.lock().await;
+    let position = call.queue().len();
+    let handle = call.enqueue(track).await;
+
+    if position > 0 {
+        let notifier = NowPlayingNotifier { http, channel, song };
+        let _ = handle.add_event(Event::Track(TrackEvent::Play), notifier);
+    }
 
-    if songs.is_empty() {
-        Ok(None)
+    position
+}
+
+fn song_track(song: &Arc<QueuedSong>) -> Track {
+    let volume = track_volume(&song.track, guild_volume(song.guild_id));
+    let mut track = Track::new_with_data(input::File::new(song.path.clone()).into(), song.clone()).volume(volume);
+    for event in [TrackEvent::Play, TrackEvent::End] {
+        let logger = PlayLogger { song: song.clone() };
+        track.events.add_event(EventData::new(Event::Track(event), logger), Duration::ZERO);
+    }
+    track
+}
+
+/// Starts a song right away, replacing the current track but keeping the rest of the queue.
+pub async fn play_song_now(call_lock: &Arc<Mutex<Call>>, song: QueuedSong) {
+    let track = song_track(&Arc::new(song));
+
+    let mut call = call_lock.lock().await;
+    let was_playing = !call.queue().is_empty();
+    call.enqueue(track).await;
+
+    if was_playing {
+        call.queue().modify_queue(|queue| {
+            if let Some(track) = queue.pop_back() {
+                queue.insert(1, track);
+            }
+        });
+        let _ = call.queue().skip();
+    }
+}
+
+pub fn stats_embed(stats: &MusicStatsRow, heading: &str) -> CreateEmbed {
+    let mut embed = CreateEmbed::new().title(heading).color(serenity::Colour::PURPLE);
+    if stats.plays == 0 {
+        return embed.description("No songs have been played in this period.");
+    }
+
+    embed = embed
+        .field("Songs played", stats.plays.to_string(), true)
+        .field("Listening time", format_listening_time(stats.listening_secs), true)
+        .field("Listeners", stats.listeners.to_string(), true);
+
+    let ranking = |lines: Vec<String>| {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| format!("{}. {}", idx + 1, line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    if !stats.top_tracks.is_empty() {
+        let lines = stats.top_tracks.iter().map(|(track, plays)| format!("**{}** — {}×", track.title, plays)).collect();
+        embed = embed.field("Most played", ranking(lines), false);
+    }
+    if !stats.top_listeners.is_empty() {
+        let lines = stats.top_listeners.iter().map(|(user, plays)| format!("<@{}> — {}×", user, plays)).collect();
+        embed = embed.field("Most active listeners", ranking(lines), true);
+    }
+    if !stats.top_series.is_empty() {
+        let lines = stats.top_series.iter().map(|(series, plays)| format!("{} — {}×", series, plays)).collect();
+        embed = embed.field("Favourite series", ranking(lines), true);
+    }
+    if !stats.sources.is_empty() {
+        let lines = stats.sources.iter().map(|(source, plays)| format!("`{}` — {}×", source, plays)).collect();
+        embed = embed.field("Played through", ranking(lines), true);
+    }
+    embed
+}
+
+pub fn format_listening_time(secs: i64) -> String {
+    let minutes = secs / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+/// Custom id prefixes of the buttons and menus attached to song embeds; the track id follows.
+pub const PLAY_BUTTON_PREFIX: &str = "music:play:";
+pub const FAVORITE_BUTTON_PREFIX: &str = "music:fav:";
+pub const RATING_MENU_PREFIX: &str = "music:rate:";
+
+pub fn song_components(track_id: i64, play: bool) -> Vec<serenity::CreateActionRow> {
+    let mut buttons = Vec::new();
+    if play {
+        buttons.push(
+            serenity::CreateButton::new(format!("{}{}", PLAY_BUTTON_PREFIX, track_id))
+                .label("Play in voice")
+                .emoji('▶')
+                .style(serenity::ButtonStyle::Primary),
+        );
+    }
+    buttons.push(
+        serenity::CreateButton::new(format!("{}{}", FAVORITE_BUTTON_PREFIX, track_id))
+            .label("Favourite")
+            .emoji('❤')
+            .style(serenity::ButtonStyle::Secondary),
+    );
+
+    let stars = (1..=5)
+        .map(|n| serenity::CreateSelectMenuOption::new("⭐".repeat(n), n.to_string()))
+        .collect();
+    let menu = serenity::CreateSelectMenu::new(
+        format!("{}{}", RATING_MENU_PREFIX, track_id),
+        serenity::CreateSelectMenuKind::String { options: stars },
+    )
+    .placeholder("Rate this song");
+
+    vec![
+        serenity::CreateActionRow::Buttons(buttons),
+        serenity::CreateActionRow::SelectMenu(menu),
+    ]
+}
+
+pub fn format_position(position: std::time::Duration) -> String {
+    let secs = position.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
     } else {
-        let mut rng = rand::rng();
-        Ok(songs.choose(&mut rng).cloned())
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+pub async fn announce_now_playing(http: &serenity::Http, channel: serenity::ChannelId, song: &QueuedSong) {
+    let (embed, cover) = with_cover_art(now_playing_embed(song), &song.path).await;
+    let mut builder = serenity::CreateMessage::new()
+        .embed(embed)
+        .components(song_components(song.track.id, false));
+    if let Some(cover) = cover {
+        builder = builder.add_file(cover);
+    }
+    if let Err(e) = channel.send_message(http, builder).await {
+        tracing::warn!("Failed to announce now playing: {}", e);
+    }
+}
+
+struct NowPlayingNotifier {
+    http: Arc<serenity::Http>,
+    channel: serenity::ChannelId,
+    song: Arc<QueuedSong>,
+}
+
+#[async_trait::async_trait]
+impl EventHandler for NowPlayingNotifier {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        announce_now_playing(&self.http, self.channel, &self.song).await;
+        // Play also fires when a paused track is resumed; only announce the first start.
+        Some(Event::Cancel)
+    }
+}
+
+/// Writes the history row when a song first starts and the seconds heard once it ends, is
+/// skipped or stopped, so queued songs that never play aren't counted.
+struct PlayLogger {
+    song: Arc<QueuedSong>,
+}
+
+#[async_trait::async_trait]
+impl EventHandler for PlayLogger {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track([(state, _), ..]) = ctx else { return None };
+        let song = &self.song;
+        let mut history_id = song.history_id.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.playing.is_done() {
+            if let Some(id) = *history_id
+                && let Err(e) = set_music_play_secs(id, state.play_time.as_secs() as i64)
+            {
+                tracing::warn!("Failed to record music play time: {}", e);
+            }
+        } else if history_id.is_none() {
+            match log_music_play(song.guild_id.get() as i64, song.track.id, Some(song.requested_by.get() as i64), &song.source) {
+                Ok(id) => *history_id = Some(id),
+                Err(e) => tracing::warn!("Failed to log music play: {}", e),
+            }
+        }
+        // Play also fires when a paused track is resumed; the row is only written once.
+        Some(Event::Cancel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Once;
+    use crate::shared::db::{get_music_tracks_in_folder, init_db, save_music_tracks};
+
+    /// Points every test in the process at one scratch database.
+    fn test_db() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let path = std::env::temp_dir().join(format!("shaggy-test-{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            // SAFETY: set once, before any test opens the database.
+            unsafe { std::env::set_var("DB_PATH", &path) };
+            init_db().expect("create test database");
+        });
+    }
+
+    fn tracks_in(folder: &str, count: usize) -> Vec<MusicTrackRow> {
+        (0..count)
+            .map(|i| MusicTrackRow {
+                id: 0,
+                path: format!("{}/{:02}.mp3", folder, i),
+                folder: folder.to_string(),
+                series: folder.to_string(),
+                title: format!("Song {}", i),
+                duration_secs: Some(180),
+                size: 1000,
+                mtime: 0,
+                artist: None,
+                album: None,
+                track_number: None,
+                gain_db: None,
+            })
+            .collect()
+    }
+
+    fn track(title: &str, artist: Option<&str>, album: Option<&str>, path: &str) -> MusicTrackRow {
+        MusicTrackRow {
+            title: title.to_string(),
+            artist: artist.map(str::to_string),
+            album: album.map(str::to_string),
+            path: path.to_string(),
+            ..tracks_in("", 1).remove(0)
+        }
+    }
+
+    #[test]
+    fn fuzzy_score_ranks_matches() {
+        let footfalls = track("Footfalls", Some("Masayoshi Soken"), Some("Endwalker"), "Final Fantasy/XIV/Endwalker/07 footfalls.flac");
+        let cases = [
+            ("footfalls", 2.5),
+            ("Footfalls (Endwalker)", 1.0),
+            ("soken", 2.0),
+            ("endwalker footfalls", 1.0),
+            ("", 0.0),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(fuzzy_score(query, &footfalls), expected, "{}", query);
+        }
+        assert!(fuzzy_score("footfals", &footfalls) >= SEARCH_THRESHOLD);
+        assert!(fuzzy_score("dearly beloved", &footfalls) < SEARCH_THRESHOLD);
+    }
+
+    #[test]
+    fn votes_needed_rounds_up_and_needs_at_least_one() {
+        let cases = [(1, 50, 1), (2, 50, 1), (3, 50, 2), (4, 50, 2), (3, 34, 2), (5, 100, 5), (7, 150, 7), (3, 0, 1), (0, 50, 1)];
+        for (listeners, percent, expected) in cases {
+            assert_eq!(votes_needed(listeners, percent), expected, "{} listeners at {}%", listeners, percent);
+        }
+    }
+
+    #[test]
+    fn avoid_recent_skips_the_last_plays() {
+        test_db();
+        let guild_id = 8001;
+        save_music_tracks(&tracks_in("Avoid Recent", 30), &[]).unwrap();
+        let ids: Vec<i64> = get_music_tracks_in_folder("Avoid Recent").unwrap().iter().map(|t| t.id).collect();
+        assert_eq!(ids.len(), 30);
+
+        // The default mode skips the last 25 songs played in the guild.
+        let (played, fresh) = ids.split_at(25);
+        for &id in played {
+            log_music_play(guild_id, id, None, "test").unwrap();
+        }
+        for _ in 0..50 {
+            let track = select_song("Avoid Recent", None, Some(guild_id)).unwrap().unwrap();
+            assert!(fresh.contains(&track.id), "picked recently played track {}", track.id);
+        }
     }
-}
\ No newline at end of file
+}
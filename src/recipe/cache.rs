@@ -0,0 +1,194 @@
+use std::future::Future;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::recipe::models::{Meal, MealMin, RecipeExclusions};
+use crate::recipe::provider::RecipeProvider;
+use crate::shared::db::{cache_meal, cache_recipe_list, get_cached_meal, get_cached_recipe_list, random_cached_meal};
+use crate::shared::types::Error;
+
+const MEAL_TTL_HOURS: i64 = 24 * 7;
+/// Category, ingredient, area and search results change as recipes are added, so they expire sooner.
+const LIST_TTL_HOURS: i64 = 24;
+
+/// Keeps recipes and list responses of `inner` in SQLite. Fresh entries are served without a
+/// request; expired ones are refreshed, but still served when `inner` fails.
+pub struct Cached<P> {
+    inner: P,
+}
+
+impl<P> Cached<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+enum Lookup<T> {
+    Fresh(T),
+    Stale(T),
+    Missing,
+}
+
+impl<T> Lookup<T> {
+    fn into_value(self) -> Option<T> {
+        match self {
+            Lookup::Fresh(value) | Lookup::Stale(value) => Some(value),
+            Lookup::Missing => None,
+        }
+    }
+}
+
+fn lookup<T: DeserializeOwned>(row: Result<Option<(String, String)>, Error>, ttl_hours: i64) -> Lookup<T> {
+    let (json, cached_at) = match row {
+        Ok(Some(row)) => row,
+        Ok(None) => return Lookup::Missing,
+        Err(e) => {
+            tracing::warn!("Failed to read the recipe cache: {}", e);
+            return Lookup::Missing;
+        }
+    };
+    let Ok(value) = serde_json::from_str(&json) else { return Lookup::Missing };
+
+    let fresh = DateTime::parse_from_rfc3339(&cached_at)
+        .is_ok_and(|at| (Utc::now() - at.with_timezone(&Utc)).num_hours() < ttl_hours);
+    if fresh { Lookup::Fresh(value) } else { Lookup::Stale(value) }
+}
+
+fn store_meal(meal: &Meal) {
+    let Some(id) = meal.id.as_deref() else { return };
+    let result = serde_json::to_string(meal)
+        .map_err(Error::from)
+        .and_then(|json| cache_meal(id, &meal.name, meal.category.as_deref(), &json));
+    if let Err(e) = result {
+        tracing::warn!("Failed to cache recipe {}: {}", id, e);
+    }
+}
+
+impl<P: RecipeProvider> Cached<P> {
+    async fn list<T, F, Fut>(&self, query: String, fetch: F) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + DeserializeOwned + Send,
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<Vec<T>, Error>> + Send,
+    {
+        let cached = lookup::<Vec<T>>(get_cached_recipe_list(&query), LIST_TTL_HOURS);
+        if let Lookup::Fresh(items) = cached {
+            return Ok(items);
+        }
+
+        match fetch().await {
+            Ok(items) => {
+                let result = serde_json::to_string(&items)
+                    .map_err(Error::from)
+                    .and_then(|json| cache_recipe_list(&query, &json));
+                if let Err(e) = result {
+                    tracing::warn!("Failed to cache recipe list {}: {}", query, e);
+                }
+                Ok(items)
+            }
+            Err(e) => match cached.into_value() {
+                Some(items) => {
+                    tracing::warn!("Recipe provider failed, serving cached {}: {}", query, e);
+                    Ok(items)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: RecipeProvider> RecipeProvider for Cached<P> {
+    async fn random(&self) -> Result<Option<Meal>, Error> {
+        match self.inner.random().await {
+            Ok(meal) => {
+                if let Some(meal) = &meal {
+                    store_meal(meal);
+                }
+                Ok(meal)
+            }
+            Err(e) => match random_cached_meal(false).ok().flatten().and_then(|json| serde_json::from_str(&json).ok()) {
+                Some(meal) => {
+                    tracing::warn!("Recipe provider failed, serving a cached recipe: {}", e);
+                    Ok(Some(meal))
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn by_category(&self, category: &str) -> Result<Vec<MealMin>, Error> {
+        let query = format!("category:{}", category.trim().to_lowercase());
+        self.list(query, || self.inner.by_category(category)).await
+    }
+
+    async fn by_ingredient(&self, ingredient: &str) -> Result<Vec<MealMin>, Error> {
+        let query = format!("ingredient:{}", ingredient.trim().to_lowercase());
+        self.list(query, || self.inner.by_ingredient(ingredient)).await
+    }
+
+    async fn by_area(&self, area: &str) -> Result<Vec<MealMin>, Error> {
+        let query = format!("area:{}", area.trim().to_lowercase());
+        self.list(query, || self.inner.by_area(area)).await
+    }
+
+    async fn by_id(&self, id: &str) -> Result<Option<Meal>, Error> {
+        let cached = lookup::<Meal>(get_cached_meal(id), MEAL_TTL_HOURS);
+        if let Lookup::Fresh(meal) = cached {
+            return Ok(Some(meal));
+        }
+
+        match self.inner.by_id(id).await {
+            Ok(meal) => {
+                if let Some(meal) = &meal {
+                    store_meal(meal);
+                }
+                Ok(meal)
+            }
+            Err(e) => match cached.into_value() {
+                Some(meal) => {
+                    tracing::warn!("Recipe provider failed, serving cached recipe {}: {}", id, e);
+                    Ok(Some(meal))
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn search(&self, name: &str) -> Result<Vec<Meal>, Error> {
+        let query = format!("search:{}", name.trim().to_lowercase());
+        self.list(query, || async {
+            let meals = self.inner.search(name).await?;
+            for meal in &meals {
+                store_meal(meal);
+            }
+            Ok(meals)
+        })
+        .await
+    }
+
+    async fn suggest(&self, partial: &str) -> Result<Vec<Meal>, Error> {
+        // Reuse finished searches, but don't fill the cache with every prefix typed.
+        let query = format!("search:{}", partial.trim().to_lowercase());
+        if let Lookup::Fresh(meals) = lookup::<Vec<Meal>>(get_cached_recipe_list(&query), LIST_TTL_HOURS) {
+            return Ok(meals);
+        }
+        self.inner.suggest(partial).await
+    }
+}
+
+const CACHE_REROLLS: usize = 10;
+
+/// A cached recipe that hasn't been the daily recipe yet and `exclusions` allows, for when the
+/// provider can't offer one.
+pub fn random_unsent_cached_meal(exclusions: &RecipeExclusions) -> Result<Option<Meal>, Error> {
+    for _ in 0..CACHE_REROLLS {
+        let Some(json) = random_cached_meal(true)? else { return Ok(None) };
+        if let Ok(meal) = serde_json::from_str::<Meal>(&json)
+            && exclusions.allows(&meal)
+        {
+            return Ok(Some(meal));
+        }
+    }
+    Ok(None)
+}
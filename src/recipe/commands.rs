@@ -1,101 +1,218 @@
-use anyhow::Context as _;
+use std::time::Duration;
+use poise::futures_util::StreamExt;
+use poise::serenity_prelude as serenity;
 use poise::ChoiceParameter;
-use rand::Rng;
 
-use crate::recipe::models::{MealCategory, MealListResponse, MealsResponse};
-use crate::shared::utils::{fetch_from_mealdb, format_meal, mealdb_base_url, special_user_id};
+use crate::recipe::models::{
+    ExclusionKind, ExclusionScope, Meal, MealCategory, RecipeExclusions, ShoppingDelivery, UnitSystem, MEAL_AREAS,
+};
+use crate::recipe::provider::{meals_matching, random_allowed_meal, random_meal_from, recipe_provider};
+use crate::recipe::utils::{load_exclusions, recipe_components, recipe_page, RecipeView, PAGE_BUTTON_PREFIX};
+use crate::recipe::shopping::{
+    add_meal_to_shopping_list, shopping_list_lines, shopping_list_recipes, shopping_list_text, SHOPPING_BUTTON_PREFIX,
+};
+use crate::shared::db::{
+    add_recipe_exclusion, clear_shopping_list, get_recipe_exclusions, get_shopping_list, remove_recipe_exclusion,
+    remove_shopping_item,
+};
+use crate::shared::utils::special_user_id;
 use crate::shared::types::{Context, Error};
 
 /// Get meal recipes.
-#[poise::command(slash_command, subcommands("random", "by_category", "by_ingredient"))]
+#[poise::command(
+    slash_command,
+    subcommands("random", "search", "by_category", "by_ingredient", "by_area", "diet", "server_diet")
+)]
 pub async fn recipe(ctx: Context<'_>) -> Result<(), Error> {
-    ctx.say("Use a recipe subcommand: `random`, `by-category`, or `by-ingredient`.")
+    ctx.say("Use a recipe subcommand: `random`, `search`, `by-category`, `by-ingredient`, `by-area`, `diet`, or `server-diet`.")
         .await?;
     Ok(())
 }
 
 /// Get a random recipe
 #[poise::command(slash_command, prefix_command)]
-pub async fn random(ctx: Context<'_>) -> Result<(), Error> {
+pub async fn random(
+    ctx: Context<'_>,
+    #[description = "Scale the ingredients for this many servings"]
+    #[min = 1]
+    #[max = 50]
+    servings: Option<u32>,
+    #[description = "Convert the measures to metric or US units"] units: Option<UnitSystem>,
+) -> Result<(), Error> {
     ctx.defer().await?;
+    let view = RecipeView::new(servings, units);
+    let exclusions = load_exclusions(ExclusionScope::User, ctx.author().id.get());
 
-    if let Some(special) = special_user_id("ESME_USER_ID") {
-        if ctx.author().id == special {
-            let ingredient = "mushrooms";
-            let url = format!("{}filter.php?i={}", mealdb_base_url(), ingredient);
-            let response = fetch_from_mealdb(&url)
-                .await
-                .context(format!("Failed to fetch meals for ingredient {}", ingredient))?;
-            let response_text = response
-                .text()
-                .await
-                .context("Failed to read ingredient meal response text")?;
-            if response_text == "{\"meals\":null}" {
-                ctx.say(format!(
-                    "Couldn't find any recipes with ingredient '{}'.",
-                    ingredient
-                ))
-                .await?;
-                return Ok(());
-            }
-
-            let meals_response: MealListResponse =
-                serde_json::from_str(&response_text).context("Failed to parse ingredient meal JSON")?;
-
-            match meals_response.meals {
-                Some(meals) if !meals.is_empty() => {
-                    let meal_id = &meals[rand::rng().random_range(0..meals.len())].id;
-
-                    let url = format!("{}lookup.php?i={}", mealdb_base_url(), meal_id);
-                    let response = fetch_from_mealdb(&url)
-                        .await
-                        .context(format!("Failed to fetch meal with id {}", meal_id))?;
-                    let meals_response: MealsResponse =
-                        response.json().await.context("Failed to parse meal JSON")?;
-
-                    match meals_response.meals {
-                        Some(meals) if !meals.is_empty() => {
-                            let meal = &meals[0];
-                            let reply_content = format_meal(meal, false, false);
-                            ctx.send(poise::CreateReply::default().embed(reply_content))
-                                .await?;
-                        }
-                        _ => {
-                            ctx.say("Couldn't find a recipe.").await?;
-                        }
-                    }
-                }
-                _ => {
-                    ctx.say(format!(
-                        "Couldn't find any recipes with ingredient '{}'.",
-                        ingredient
-                    ))
-                    .await?;
-                }
-            }
+    if let Some(special) = special_user_id("ESME_USER_ID") && ctx.author().id == special {
+        let ingredient = "mushrooms";
+        let provider = recipe_provider();
+        let meals = provider.by_ingredient(ingredient).await?;
+        if meals.is_empty() {
+            ctx.say(format!(
+                "Couldn't find any recipes with ingredient '{}'.",
+                ingredient
+            ))
+            .await?;
             return Ok(());
         }
+
+        let meal = random_meal_from(provider.as_ref(), &meals, &exclusions).await?;
+        send_suggestion(ctx, meal, &exclusions, &view).await?;
+        return Ok(());
     }
 
-    let url = format!("{}random.php", mealdb_base_url());
-    let response = fetch_from_mealdb(&url)
-        .await
-        .context("Failed to fetch random meal")?;
-    let meals_response: MealsResponse =
-        response.json().await.context("Failed to parse random meal JSON")?;
+    match random_allowed_meal(recipe_provider().as_ref(), &exclusions).await? {
+        Some(meal) => send_meal(ctx, Some(meal), &view).await?,
+        None if !exclusions.is_empty() => send_suggestion(ctx, None, &exclusions, &view).await?,
+        None => {
+            ctx.say("Couldn't find a random recipe.").await?;
+        }
+    }
+
+    Ok(())
+}
+
+const SEARCH_MENU_SECS: u64 = 60;
+/// Discord allows at most 25 options in a select menu (and 25 autocomplete choices).
+const MAX_CHOICES: usize = 25;
+
+/// Find a recipe by name
+#[poise::command(slash_command, prefix_command)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "Recipe name"]
+    #[autocomplete = "autocomplete_recipe"]
+    name: String,
+    #[description = "Scale the ingredients for this many servings"]
+    #[min = 1]
+    #[max = 50]
+    servings: Option<u32>,
+    #[description = "Convert the measures to metric or US units"] units: Option<UnitSystem>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let view = RecipeView::new(servings, units);
+    let provider = recipe_provider();
+
+    // Autocomplete suggestions carry the recipe id.
+    if let Some(id) = name.trim().strip_prefix("id:") {
+        let meal = provider.by_id(id.trim()).await?;
+        send_meal(ctx, meal, &view).await?;
+        return Ok(());
+    }
 
-    match meals_response.meals {
-        Some(meals) if !meals.is_empty() => {
-            let meal = &meals[0];
-            let reply_content = format_meal(meal, false, false);
-            ctx.send(poise::CreateReply::default().embed(reply_content))
+    let mut meals = provider.search(name.trim()).await?;
+    match meals.len() {
+        0 => {
+            ctx.say(format!("Couldn't find any recipes matching '{}'.", name.trim()))
                 .await?;
         }
+        1 => send_meal(ctx, meals.pop(), &view).await?,
         _ => {
-            ctx.say("Couldn't find a random recipe.").await?;
+            meals.truncate(MAX_CHOICES);
+            pick_meal(ctx, meals, &view).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn autocomplete_recipe(_ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    if partial.trim().is_empty() {
+        return Vec::new();
+    }
+    match recipe_provider().suggest(partial.trim()).await {
+        Ok(meals) => meals
+            .into_iter()
+            .filter_map(|meal| {
+                let id = meal.id?;
+                Some(serenity::AutocompleteChoice::new(choice_label(&meal.name), format!("id:{}", id)))
+            })
+            .take(MAX_CHOICES)
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Recipe autocomplete failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Discord rejects choice and option labels longer than 100 characters.
+fn choice_label(name: &str) -> String {
+    if name.chars().count() > 100 {
+        name.chars().take(99).collect::<String>() + "…"
+    } else {
+        name.to_string()
+    }
+}
+
+async fn pick_meal(ctx: Context<'_>, meals: Vec<Meal>, view: &RecipeView) -> Result<(), Error> {
+    let options = meals
+        .iter()
+        .enumerate()
+        .map(|(idx, meal)| {
+            let option = serenity::CreateSelectMenuOption::new(choice_label(&meal.name), idx.to_string());
+            match meal.category.as_deref().filter(|c| !c.is_empty()) {
+                Some(category) => option.description(category),
+                None => option,
+            }
+        })
+        .collect();
+    let custom_id = format!("recipe:search:{}", ctx.id());
+    let menu = serenity::CreateSelectMenu::new(&custom_id, serenity::CreateSelectMenuKind::String { options })
+        .placeholder("Pick a recipe");
+
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(format!("Found {} recipes, pick one:", meals.len()))
+                .components(vec![serenity::CreateActionRow::SelectMenu(menu)]),
+        )
+        .await?;
+    let message_id = reply.message().await?.id;
+
+    let mut presses = serenity::ComponentInteractionCollector::new(ctx.serenity_context())
+        .message_id(message_id)
+        .custom_ids(vec![custom_id])
+        .timeout(Duration::from_secs(SEARCH_MENU_SECS))
+        .stream();
+
+    while let Some(press) = presses.next().await {
+        if press.user.id != ctx.author().id {
+            let response = serenity::CreateInteractionResponse::Message(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content("Only the person who searched can pick. Try /recipe search yourself!")
+                    .ephemeral(true),
+            );
+            let _ = press.create_response(ctx, response).await;
+            continue;
         }
+
+        let choice = match &press.data.kind {
+            serenity::ComponentInteractionDataKind::StringSelect { values } => {
+                values.first().and_then(|value| value.parse::<usize>().ok())
+            }
+            _ => None,
+        };
+        let Some(meal) = choice.and_then(|idx| meals.get(idx)) else { continue };
+
+        let response = serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new()
+                .content("")
+                .embed(recipe_page(meal, 0, view))
+                .components(recipe_components(meal, 0, view)),
+        );
+        press.create_response(ctx, response).await?;
+        return Ok(());
     }
 
+    // Nobody picked in time: drop the menu so it doesn't look usable.
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content("No recipe was picked. Run /recipe search again to choose one.")
+                .components(Vec::new()),
+        )
+        .await?;
     Ok(())
 }
 
@@ -104,52 +221,28 @@ pub async fn random(ctx: Context<'_>) -> Result<(), Error> {
 pub async fn by_category(
     ctx: Context<'_>,
     #[description = "Category to search for"] category: MealCategory,
+    #[description = "Scale the ingredients for this many servings"]
+    #[min = 1]
+    #[max = 50]
+    servings: Option<u32>,
+    #[description = "Convert the measures to metric or US units"] units: Option<UnitSystem>,
 ) -> Result<(), Error> {
     ctx.defer().await?;
     let category_name = category.name();
-    let url = format!(
-        "{}filter.php?c={}",
-        mealdb_base_url(),
-        category_name
-    );
-    let response = fetch_from_mealdb(&url)
-        .await
-        .context(format!("Failed to fetch meals for category {}", category_name))?;
-    let meals_response: MealListResponse =
-        response.json().await.context("Failed to parse category meal JSON")?;
-
-    match meals_response.meals {
-        Some(meals) if !meals.is_empty() => {
-            let meal_id = &meals[rand::rng().random_range(0..meals.len())].id;
-
-            let url = format!("{}lookup.php?i={}", mealdb_base_url(), meal_id);
-            let response = fetch_from_mealdb(&url)
-                .await
-                .context(format!("Failed to fetch meal with id {}", meal_id))?;
-            let meals_response: MealsResponse =
-                response.json().await.context("Failed to parse meal JSON")?;
-
-            match meals_response.meals {
-                Some(meals) if !meals.is_empty() => {
-                    let meal = &meals[0];
-                    let reply_content = format_meal(meal, false, false);
-                    ctx.send(poise::CreateReply::default().embed(reply_content))
-                        .await?;
-                }
-                _ => {
-                    ctx.say("Couldn't find a recipe.").await?;
-                }
-            }
-        }
-        _ => {
-            ctx.say(format!(
-                "Couldn't find any recipes in category '{}'.",
-                category_name
-            ))
-            .await?;
-        }
+    let provider = recipe_provider();
+    let meals = provider.by_category(category_name).await?;
+    if meals.is_empty() {
+        ctx.say(format!(
+            "Couldn't find any recipes in category '{}'.",
+            category_name
+        ))
+        .await?;
+        return Ok(());
     }
 
+    let exclusions = load_exclusions(ExclusionScope::User, ctx.author().id.get());
+    let meal = random_meal_from(provider.as_ref(), &meals, &exclusions).await?;
+    send_suggestion(ctx, meal, &exclusions, &RecipeView::new(servings, units)).await?;
     Ok(())
 }
 
@@ -157,63 +250,503 @@ pub async fn by_category(
 #[poise::command(slash_command, prefix_command, rename = "by-ingredient")]
 pub async fn by_ingredient(
     ctx: Context<'_>,
-    #[description = "Ingredient to search for"] ingredient: String,
+    #[description = "Ingredient to search for; separate several with commas or +"] ingredient: String,
+    #[description = "Only recipes from this cuisine"]
+    #[autocomplete = "autocomplete_area"]
+    area: Option<String>,
+    #[description = "Scale the ingredients for this many servings"]
+    #[min = 1]
+    #[max = 50]
+    servings: Option<u32>,
+    #[description = "Convert the measures to metric or US units"] units: Option<UnitSystem>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let ingredients: Vec<String> = ingredient
+        .split([',', '+'])
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let area = area.as_deref().map(str::trim).filter(|area| !area.is_empty());
+    if ingredients.is_empty() {
+        ctx.say("Tell me at least one ingredient.").await?;
+        return Ok(());
+    }
+
+    let provider = recipe_provider();
+    let meals = meals_matching(provider.as_ref(), &ingredients, area).await?;
+    if meals.is_empty() {
+        let mut msg = format!("Couldn't find any recipes with {}", describe_ingredients(&ingredients));
+        if let Some(area) = area {
+            msg.push_str(&format!(" from the {} cuisine", area));
+        }
+        msg.push('.');
+        ctx.say(msg).await?;
+        return Ok(());
+    }
+
+    let exclusions = load_exclusions(ExclusionScope::User, ctx.author().id.get());
+    let meal = random_meal_from(provider.as_ref(), &meals, &exclusions).await?;
+    send_suggestion(ctx, meal, &exclusions, &RecipeView::new(servings, units)).await?;
+    Ok(())
+}
+
+/// Get recipes by area (cuisine)
+#[poise::command(slash_command, prefix_command, rename = "by-area")]
+pub async fn by_area(
+    ctx: Context<'_>,
+    #[description = "Cuisine, e.g. Japanese"]
+    #[autocomplete = "autocomplete_area"]
+    area: String,
+    #[description = "Scale the ingredients for this many servings"]
+    #[min = 1]
+    #[max = 50]
+    servings: Option<u32>,
+    #[description = "Convert the measures to metric or US units"] units: Option<UnitSystem>,
 ) -> Result<(), Error> {
     ctx.defer().await?;
-    let url = format!(
-        "{}filter.php?i={}",
-        mealdb_base_url(),
-        ingredient
-    );
-    let response = fetch_from_mealdb(&url)
-        .await
-        .context(format!("Failed to fetch meals for ingredient {}", ingredient))?;
-    let response_text = response
-        .text()
-        .await
-        .context("Failed to read ingredient meal response text")?;
-    if response_text == "{\"meals\":null}" {
+    let provider = recipe_provider();
+    let meals = provider.by_area(area.trim()).await?;
+    if meals.is_empty() {
         ctx.say(format!(
-            "Couldn't find any recipes with ingredient '{}'.",
-            ingredient
+            "Couldn't find any recipes from the '{}' cuisine.",
+            area.trim()
         ))
         .await?;
         return Ok(());
     }
 
-    let meals_response: MealListResponse =
-        serde_json::from_str(&response_text).context("Failed to parse ingredient meal JSON")?;
+    let exclusions = load_exclusions(ExclusionScope::User, ctx.author().id.get());
+    let meal = random_meal_from(provider.as_ref(), &meals, &exclusions).await?;
+    send_suggestion(ctx, meal, &exclusions, &RecipeView::new(servings, units)).await?;
+    Ok(())
+}
 
-    match meals_response.meals {
-        Some(meals) if !meals.is_empty() => {
-            let meal_id = &meals[rand::rng().random_range(0..meals.len())].id;
+async fn autocomplete_area(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.trim().to_lowercase();
+    MEAL_AREAS
+        .iter()
+        .filter(|area| area.to_lowercase().starts_with(&partial))
+        .take(MAX_CHOICES)
+        .map(|area| area.to_string())
+        .collect()
+}
 
-            let url = format!("{}lookup.php?i={}", mealdb_base_url(), meal_id);
-            let response = fetch_from_mealdb(&url)
-                .await
-                .context(format!("Failed to fetch meal with id {}", meal_id))?;
-            let meals_response: MealsResponse =
-                response.json().await.context("Failed to parse meal JSON")?;
+/// "ingredient 'rice'" or "ingredients 'chicken', 'rice'".
+fn describe_ingredients(ingredients: &[String]) -> String {
+    let quoted = ingredients.iter().map(|i| format!("'{}'", i)).collect::<Vec<_>>().join(", ");
+    if ingredients.len() == 1 {
+        format!("ingredient {}", quoted)
+    } else {
+        format!("ingredients {}", quoted)
+    }
+}
 
-            match meals_response.meals {
-                Some(meals) if !meals.is_empty() => {
-                    let meal = &meals[0];
-                    let reply_content = format_meal(meal, false, false);
-            ctx.send(poise::CreateReply::default().embed(reply_content))
-                        .await?;
-                }
-                _ => {
-                    ctx.say("Couldn't find a recipe.").await?;
+async fn send_suggestion(
+    ctx: Context<'_>,
+    meal: Option<Meal>,
+    exclusions: &RecipeExclusions,
+    view: &RecipeView,
+) -> Result<(), Error> {
+    if meal.is_none() && !exclusions.is_empty() {
+        ctx.say("Couldn't find a recipe that fits your dietary exclusions. See `/recipe diet show`.")
+            .await?;
+        return Ok(());
+    }
+    send_meal(ctx, meal, view).await
+}
+
+async fn send_meal(ctx: Context<'_>, meal: Option<Meal>, view: &RecipeView) -> Result<(), Error> {
+    match meal {
+        Some(meal) => {
+            let reply_content = recipe_page(&meal, 0, view);
+            ctx.send(
+                poise::CreateReply::default()
+                    .embed(reply_content)
+                    .components(recipe_components(&meal, 0, view)),
+            )
+            .await?;
+        }
+        None => {
+            ctx.say("Couldn't find a recipe.").await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_recipe_component(ctx: &serenity::Context, component: &serenity::ComponentInteraction) -> Result<(), Error> {
+    let custom_id = component.data.custom_id.as_str();
+    if let Some(rest) = custom_id.strip_prefix(PAGE_BUTTON_PREFIX) {
+        turn_page(ctx, component, rest).await
+    } else if let Some(rest) = custom_id.strip_prefix(SHOPPING_BUTTON_PREFIX) {
+        shop_from_button(ctx, component, rest).await
+    } else {
+        Ok(())
+    }
+}
+
+async fn turn_page(ctx: &serenity::Context, component: &serenity::ComponentInteraction, rest: &str) -> Result<(), Error> {
+    let mut parts = rest.splitn(3, ':');
+    let (Some(page), Some(flags), Some(id)) = (parts.next().and_then(|p| p.parse::<usize>().ok()), parts.next(), parts.next())
+    else {
+        return Ok(());
+    };
+    let view = RecipeView::from_flags(flags);
+
+    let response = match recipe_provider().by_id(id).await {
+        Ok(Some(meal)) => serenity::CreateInteractionResponse::UpdateMessage(
+            serenity::CreateInteractionResponseMessage::new()
+                .embed(recipe_page(&meal, page, &view))
+                .components(recipe_components(&meal, page, &view)),
+        ),
+        result => {
+            if let Err(e) = result {
+                tracing::error!("Recipe component {} failed: {}", component.data.custom_id, e);
+            }
+            serenity::CreateInteractionResponse::Message(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content("Couldn't load that recipe right now, please try again later.")
+                    .ephemeral(true),
+            )
+        }
+    };
+    component.create_response(&ctx.http, response).await?;
+    Ok(())
+}
+
+async fn shop_from_button(ctx: &serenity::Context, component: &serenity::ComponentInteraction, rest: &str) -> Result<(), Error> {
+    let Some((flags, id)) = rest.split_once(':') else {
+        return Ok(());
+    };
+    let view = RecipeView::from_flags(flags);
+
+    let msg = match recipe_provider().by_id(id).await {
+        Ok(Some(meal)) => match add_meal_to_shopping_list(component.user.id.get(), &meal, &view) {
+            Ok(count) => format!(
+                "🛒 Added {} ingredient{} from **{}** to your shopping list. See it with `/shopping show`.",
+                count,
+                if count == 1 { "" } else { "s" },
+                meal.name
+            ),
+            Err(e) => {
+                tracing::error!("Failed to save shopping list: {}", e);
+                "Failed to save your shopping list. Please try again later.".to_string()
+            }
+        },
+        Ok(None) => "That recipe is no longer available.".to_string(),
+        Err(e) => {
+            tracing::error!("Recipe component {} failed: {}", component.data.custom_id, e);
+            "Couldn't load that recipe right now, please try again later.".to_string()
+        }
+    };
+    component
+        .create_response(
+            &ctx.http,
+            serenity::CreateInteractionResponse::Message(
+                serenity::CreateInteractionResponseMessage::new().content(msg).ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Manage the ingredients and categories you don't want suggested.
+#[poise::command(slash_command, rename = "diet", subcommands("diet_add", "diet_remove", "diet_show"))]
+pub async fn diet(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/recipe diet add`, `/recipe diet remove` or `/recipe diet show`.").await?;
+    Ok(())
+}
+
+/// Stop suggesting recipes with an ingredient or from a category.
+#[poise::command(slash_command, rename = "add")]
+pub async fn diet_add(
+    ctx: Context<'_>,
+    #[description = "Exclude an ingredient or a whole category"] kind: ExclusionKind,
+    #[description = "Ingredient or category name, e.g. peanut or Pork"] value: String,
+) -> Result<(), Error> {
+    add_exclusion(ctx, ExclusionScope::User, ctx.author().id.get(), kind, &value).await
+}
+
+/// Allow an excluded ingredient or category again.
+#[poise::command(slash_command, rename = "remove")]
+pub async fn diet_remove(
+    ctx: Context<'_>,
+    #[description = "Ingredient or category"] kind: ExclusionKind,
+    #[description = "Ingredient or category name"] value: String,
+) -> Result<(), Error> {
+    remove_exclusion(ctx, ExclusionScope::User, ctx.author().id.get(), kind, &value).await
+}
+
+/// Show your dietary exclusions.
+#[poise::command(slash_command, rename = "show")]
+pub async fn diet_show(ctx: Context<'_>) -> Result<(), Error> {
+    show_exclusions(ctx, ExclusionScope::User, ctx.author().id.get(), "🥗 Your Dietary Exclusions").await
+}
+
+/// Manage what the daily recipe avoids in this server (administrators only).
+#[poise::command(
+    slash_command,
+    guild_only,
+    rename = "server-diet",
+    required_permissions = "ADMINISTRATOR",
+    subcommands("server_diet_add", "server_diet_remove", "server_diet_show")
+)]
+pub async fn server_diet(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/recipe server-diet add`, `/recipe server-diet remove` or `/recipe server-diet show`.")
+        .await?;
+    Ok(())
+}
+
+/// Keep an ingredient or category out of this server's daily recipe.
+#[poise::command(slash_command, guild_only, rename = "add", required_permissions = "ADMINISTRATOR")]
+pub async fn server_diet_add(
+    ctx: Context<'_>,
+    #[description = "Exclude an ingredient or a whole category"] kind: ExclusionKind,
+    #[description = "Ingredient or category name, e.g. peanut or Pork"] value: String,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    add_exclusion(ctx, ExclusionScope::Guild, guild_id.get(), kind, &value).await
+}
+
+/// Allow an ingredient or category in this server's daily recipe again.
+#[poise::command(slash_command, guild_only, rename = "remove", required_permissions = "ADMINISTRATOR")]
+pub async fn server_diet_remove(
+    ctx: Context<'_>,
+    #[description = "Ingredient or category"] kind: ExclusionKind,
+    #[description = "Ingredient or category name"] value: String,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    remove_exclusion(ctx, ExclusionScope::Guild, guild_id.get(), kind, &value).await
+}
+
+/// Show what this server's daily recipe avoids.
+#[poise::command(slash_command, guild_only, rename = "show", required_permissions = "ADMINISTRATOR")]
+pub async fn server_diet_show(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => {
+            ctx.say("This command can only be used in a server.").await?;
+            return Ok(());
+        }
+    };
+    show_exclusions(ctx, ExclusionScope::Guild, guild_id.get(), "🥗 Daily Recipe Exclusions").await
+}
+
+async fn add_exclusion(
+    ctx: Context<'_>,
+    scope: ExclusionScope,
+    owner_id: u64,
+    kind: ExclusionKind,
+    value: &str,
+) -> Result<(), Error> {
+    let value = value.trim();
+    if value.is_empty() {
+        ctx.send(poise::CreateReply::default().content("Tell me what to exclude.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let msg = match add_recipe_exclusion(scope.as_str(), owner_id as i64, kind.as_str(), value) {
+        Ok(true) => format!("✅ Recipes with {} '{}' won't be suggested anymore.", kind.as_str(), value),
+        Ok(false) => format!("{} '{}' is already excluded.", capitalize(kind.as_str()), value),
+        Err(e) => {
+            tracing::error!("Failed to save recipe exclusion: {}", e);
+            "Failed to save the exclusion. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+async fn remove_exclusion(
+    ctx: Context<'_>,
+    scope: ExclusionScope,
+    owner_id: u64,
+    kind: ExclusionKind,
+    value: &str,
+) -> Result<(), Error> {
+    let value = value.trim();
+    let msg = match remove_recipe_exclusion(scope.as_str(), owner_id as i64, kind.as_str(), value) {
+        Ok(true) => format!("✅ {} '{}' is allowed again.", capitalize(kind.as_str()), value),
+        Ok(false) => format!("{} '{}' wasn't excluded.", capitalize(kind.as_str()), value),
+        Err(e) => {
+            tracing::error!("Failed to remove recipe exclusion: {}", e);
+            "Failed to remove the exclusion. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+async fn show_exclusions(ctx: Context<'_>, scope: ExclusionScope, owner_id: u64, title: &str) -> Result<(), Error> {
+    let exclusions = RecipeExclusions::from_rows(get_recipe_exclusions(scope.as_str(), owner_id as i64)?);
+    let list = |values: &[String]| if values.is_empty() { "None".to_string() } else { values.join(", ") };
+    let embed = serenity::CreateEmbed::new()
+        .title(title)
+        .field("Ingredients", list(&exclusions.ingredients), false)
+        .field("Categories", list(&exclusions.categories), false)
+        .color(0x00FF00);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
+    Ok(())
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Discord's limit for a plain message; longer lists are sent as a file instead.
+const MESSAGE_MAX_CHARS: usize = 2000;
+/// Embed descriptions stop well before Discord's 4096 character limit.
+const LIST_DESCRIPTION_CHARS: usize = 3500;
+
+/// Your shopping list, built with the 🛒 button on recipes.
+#[poise::command(slash_command, subcommands("shopping_show", "shopping_remove", "shopping_clear"))]
+pub async fn shopping(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/shopping show`, `/shopping remove` or `/shopping clear`.").await?;
+    Ok(())
+}
+
+/// Show your shopping list, or send it to your DMs or as a text file.
+#[poise::command(slash_command, rename = "show")]
+pub async fn shopping_show(
+    ctx: Context<'_>,
+    #[description = "Where to send the list (default: here, only you can see it)"] send: Option<ShoppingDelivery>,
+) -> Result<(), Error> {
+    let items = get_shopping_list(ctx.author().id.get() as i64)?;
+    if items.is_empty() {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Your shopping list is empty. Press 🛒 **Add to shopping list** on a recipe to fill it.")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let text = shopping_list_text(&items);
+    let attachment = || serenity::CreateAttachment::bytes(text.clone().into_bytes(), "shopping-list.txt");
+    match send.unwrap_or_default() {
+        ShoppingDelivery::Here => {
+            let mut description = String::new();
+            let lines = shopping_list_lines(&items);
+            for (idx, line) in lines.iter().enumerate() {
+                let entry = format!("• {}\n", line);
+                if description.chars().count() + entry.chars().count() > LIST_DESCRIPTION_CHARS {
+                    description.push_str(&format!(
+                        "… and {} more. Use `/shopping show send: Text file` for the whole list.",
+                        lines.len() - idx
+                    ));
+                    break;
                 }
+                description.push_str(&entry);
             }
+            let recipes = shopping_list_recipes(&items).join(", ");
+            let embed = serenity::CreateEmbed::new()
+                .title("🛒 Your Shopping List")
+                .description(description)
+                .footer(serenity::CreateEmbedFooter::new(format!("For: {}", truncate_footer(&recipes))))
+                .color(0x00FF00);
+            ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true)).await?;
         }
-        _ => {
-            ctx.say(format!(
-                "Couldn't find any recipes with ingredient '{}'.",
-                ingredient
-            ))
+        ShoppingDelivery::Dm => {
+            let message = if text.chars().count() > MESSAGE_MAX_CHARS {
+                serenity::CreateMessage::new().content("🛒 Your shopping list:").add_file(attachment())
+            } else {
+                serenity::CreateMessage::new().content(format!("🛒 {}", text))
+            };
+            let msg = match ctx.author().direct_message(ctx, message).await {
+                Ok(_) => "📬 Sent your shopping list to your DMs.",
+                Err(e) => {
+                    tracing::warn!("Failed to DM shopping list: {}", e);
+                    "I couldn't DM you. Check that you allow direct messages from server members, or use `send: Text file`."
+                }
+            };
+            ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+        }
+        ShoppingDelivery::File => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content("🛒 Your shopping list:")
+                    .attachment(attachment())
+                    .ephemeral(true),
+            )
             .await?;
         }
     }
     Ok(())
 }
+
+/// Embed footers are limited to 2048 characters.
+fn truncate_footer(text: &str) -> String {
+    if text.chars().count() > 2000 {
+        text.chars().take(1999).collect::<String>() + "…"
+    } else {
+        text.to_string()
+    }
+}
+
+/// Remove an ingredient from your shopping list (e.g. once it's in the basket).
+#[poise::command(slash_command, rename = "remove")]
+pub async fn shopping_remove(
+    ctx: Context<'_>,
+    #[description = "Ingredient to remove"]
+    #[autocomplete = "autocomplete_shopping_item"]
+    ingredient: String,
+) -> Result<(), Error> {
+    let msg = match remove_shopping_item(ctx.author().id.get() as i64, &ingredient) {
+        Ok(true) => format!("✅ Removed **{}** from your shopping list.", ingredient.trim()),
+        Ok(false) => format!("**{}** isn't on your shopping list.", ingredient.trim()),
+        Err(e) => {
+            tracing::error!("Failed to update shopping list: {}", e);
+            "Failed to update your shopping list. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
+
+async fn autocomplete_shopping_item(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let partial = partial.trim().to_lowercase();
+    match get_shopping_list(ctx.author().id.get() as i64) {
+        Ok(items) => items
+            .into_iter()
+            .map(|item| item.ingredient)
+            .filter(|ingredient| ingredient.to_lowercase().contains(&partial))
+            .take(MAX_CHOICES)
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Shopping list autocomplete failed: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Empty your shopping list.
+#[poise::command(slash_command, rename = "clear")]
+pub async fn shopping_clear(ctx: Context<'_>) -> Result<(), Error> {
+    let msg = match clear_shopping_list(ctx.author().id.get() as i64) {
+        Ok(0) => "Your shopping list was already empty.".to_string(),
+        Ok(count) => format!("🧹 Cleared {} item{} from your shopping list.", count, if count == 1 { "" } else { "s" }),
+        Err(e) => {
+            tracing::error!("Failed to clear shopping list: {}", e);
+            "Failed to clear your shopping list. Please try again later.".to_string()
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(msg).ephemeral(true)).await?;
+    Ok(())
+}
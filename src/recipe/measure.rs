@@ -0,0 +1,503 @@
+use crate::recipe::models::UnitSystem;
+
+/// Units the parser recognises. Spoons stay spoons in every system.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Gram,
+    Kilogram,
+    Ounce,
+    Pound,
+    Millilitre,
+    Litre,
+    Teaspoon,
+    Tablespoon,
+    Cup,
+    FluidOunce,
+    Pint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Mass,
+    Volume,
+}
+
+/// Spellings found in TheMealDB measures, longest first so "fl oz" wins over "oz"-like prefixes.
+const UNIT_NAMES: &[(&str, Unit)] = &[
+    ("fluid ounces", Unit::FluidOunce),
+    ("fluid ounce", Unit::FluidOunce),
+    ("millilitres", Unit::Millilitre),
+    ("milliliters", Unit::Millilitre),
+    ("millilitre", Unit::Millilitre),
+    ("milliliter", Unit::Millilitre),
+    ("tablespoons", Unit::Tablespoon),
+    ("tablespoon", Unit::Tablespoon),
+    ("teaspoons", Unit::Teaspoon),
+    ("teaspoon", Unit::Teaspoon),
+    ("kilograms", Unit::Kilogram),
+    ("kilogram", Unit::Kilogram),
+    ("ounces", Unit::Ounce),
+    ("ounce", Unit::Ounce),
+    ("pounds", Unit::Pound),
+    ("pound", Unit::Pound),
+    ("litres", Unit::Litre),
+    ("liters", Unit::Litre),
+    ("litre", Unit::Litre),
+    ("liter", Unit::Litre),
+    ("grams", Unit::Gram),
+    ("gram", Unit::Gram),
+    ("kilos", Unit::Kilogram),
+    ("kilo", Unit::Kilogram),
+    ("pints", Unit::Pint),
+    ("pint", Unit::Pint),
+    ("fl oz", Unit::FluidOunce),
+    ("floz", Unit::FluidOunce),
+    ("tbsp", Unit::Tablespoon),
+    ("tbls", Unit::Tablespoon),
+    ("tbs", Unit::Tablespoon),
+    ("tsp", Unit::Teaspoon),
+    ("cups", Unit::Cup),
+    ("cup", Unit::Cup),
+    ("kgs", Unit::Kilogram),
+    ("kg", Unit::Kilogram),
+    ("lbs", Unit::Pound),
+    ("lb", Unit::Pound),
+    ("oz", Unit::Ounce),
+    ("ml", Unit::Millilitre),
+    ("ltr", Unit::Litre),
+    ("pt", Unit::Pint),
+    ("gr", Unit::Gram),
+    ("g", Unit::Gram),
+    ("l", Unit::Litre),
+];
+
+impl Unit {
+    pub fn dimension(self) -> Dimension {
+        match self {
+            Self::Gram | Self::Kilogram | Self::Ounce | Self::Pound => Dimension::Mass,
+            _ => Dimension::Volume,
+        }
+    }
+
+    /// Size in grams (mass) or millilitres (volume). Cups and pints are US measures.
+    pub fn base(self) -> f64 {
+        match self {
+            Self::Gram | Self::Millilitre => 1.0,
+            Self::Kilogram | Self::Litre => 1000.0,
+            Self::Ounce => 28.3495,
+            Self::Pound => 453.592,
+            Self::Teaspoon => 5.0,
+            Self::Tablespoon => 15.0,
+            Self::Cup => 240.0,
+            Self::FluidOunce => 29.5735,
+            Self::Pint => 473.176,
+        }
+    }
+
+    fn is_metric(self) -> bool {
+        matches!(self, Self::Gram | Self::Kilogram | Self::Millilitre | Self::Litre)
+    }
+
+    fn label(self, amount: f64) -> &'static str {
+        let plural = amount > 1.0;
+        match self {
+            Self::Gram => "g",
+            Self::Kilogram => "kg",
+            Self::Millilitre => "ml",
+            Self::Litre if plural => "litres",
+            Self::Litre => "litre",
+            Self::Ounce => "oz",
+            Self::Pound => "lb",
+            Self::Teaspoon => "tsp",
+            Self::Tablespoon => "tbsp",
+            Self::Cup if plural => "cups",
+            Self::Cup => "cup",
+            Self::FluidOunce => "fl oz",
+            Self::Pint if plural => "pints",
+            Self::Pint => "pint",
+        }
+    }
+
+    fn preferred(self, system: UnitSystem, amount: f64) -> Self {
+        let base = amount * self.base();
+        match (system, self.dimension()) {
+            (UnitSystem::Original, _) => self,
+            (_, Dimension::Volume) if matches!(self, Self::Teaspoon | Self::Tablespoon) => self,
+            (UnitSystem::Metric, Dimension::Mass) if base >= 1000.0 => Self::Kilogram,
+            (UnitSystem::Metric, Dimension::Mass) => Self::Gram,
+            (UnitSystem::Metric, Dimension::Volume) if base >= 1000.0 => Self::Litre,
+            (UnitSystem::Metric, Dimension::Volume) => Self::Millilitre,
+            (UnitSystem::Imperial, Dimension::Mass) if base >= Self::Pound.base() => Self::Pound,
+            (UnitSystem::Imperial, Dimension::Mass) => Self::Ounce,
+            (UnitSystem::Imperial, Dimension::Volume) if base < 15.0 => Self::Teaspoon,
+            (UnitSystem::Imperial, Dimension::Volume) if base < 60.0 => Self::Tablespoon,
+            (UnitSystem::Imperial, Dimension::Volume) => Self::Cup,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measure {
+    pub amount: f64,
+    /// The top of a range such as "2-3 tbs".
+    pub upper: Option<f64>,
+    pub unit: Option<Unit>,
+    pub rest: String,
+}
+
+impl Measure {
+    /// Reads a measure that starts with a quantity: whole numbers, decimals, fractions ("1/2",
+    /// "1 1/2", "½", "1½") and ranges ("2-3", "2 to 3"). Returns `None` for measures without
+    /// one, like "To taste" or "pinch".
+    pub fn parse(raw: &str) -> Option<Self> {
+        let (amount, rest) = parse_number(raw.trim())?;
+
+        let mut upper = None;
+        let mut rest = rest;
+        let after_dash = rest.trim_start();
+        let after_dash = after_dash
+            .strip_prefix('-')
+            .or_else(|| after_dash.strip_prefix('–'))
+            .or_else(|| after_dash.strip_prefix("to "));
+        if let Some((high, after)) = after_dash.and_then(|s| parse_number(s.trim_start())) {
+            upper = Some(high);
+            rest = after;
+        }
+
+        let (unit, rest) = match parse_unit(rest.trim_start()) {
+            Some((unit, after)) => (Some(unit), after),
+            None => (None, rest),
+        };
+        Some(Self { amount, upper, unit, rest: rest.trim().to_string() })
+    }
+
+    pub fn scaled(mut self, factor: f64) -> Self {
+        self.amount *= factor;
+        self.upper = self.upper.map(|upper| upper * factor);
+        self
+    }
+
+    /// The total of two measures of the same kind: masses, volumes, or unitless counts with the
+    /// same description ("2 cloves" and "1 cloves"). Mixed units are added up in the larger one.
+    pub fn combined(&self, other: &Measure) -> Option<Measure> {
+        if !self.rest.eq_ignore_ascii_case(&other.rest) {
+            return None;
+        }
+        let unit = match (self.unit, other.unit) {
+            (None, None) => None,
+            (Some(a), Some(b)) if a.dimension() == b.dimension() => Some(if b.base() > a.base() { b } else { a }),
+            _ => return None,
+        };
+        let in_unit = |measure: &Measure, amount: f64| match (measure.unit, unit) {
+            (Some(from), Some(to)) => amount * from.base() / to.base(),
+            _ => amount,
+        };
+        let upper = (self.upper.is_some() || other.upper.is_some()).then(|| {
+            in_unit(self, self.upper.unwrap_or(self.amount)) + in_unit(other, other.upper.unwrap_or(other.amount))
+        });
+        let total = Measure {
+            amount: in_unit(self, self.amount) + in_unit(other, other.amount),
+            upper,
+            unit,
+            rest: self.rest.clone(),
+        };
+        // Keep metric totals readable: 800g + 400g is 1.2kg.
+        Some(match unit {
+            Some(unit) if unit.is_metric() => total.converted(UnitSystem::Metric),
+            _ => total,
+        })
+    }
+
+    pub fn converted(mut self, system: UnitSystem) -> Self {
+        let Some(unit) = self.unit else { return self };
+        let target = unit.preferred(system, self.upper.unwrap_or(self.amount));
+        if target != unit {
+            let ratio = unit.base() / target.base();
+            self.amount *= ratio;
+            self.upper = self.upper.map(|upper| upper * ratio);
+            self.unit = Some(target);
+        }
+        self
+    }
+}
+
+impl std::fmt::Display for Measure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let number = |amount: f64| match self.unit {
+            Some(unit) if unit.is_metric() => format_decimal(amount),
+            _ => format_fraction(amount),
+        };
+        write!(f, "{}", number(self.amount))?;
+        if let Some(upper) = self.upper {
+            write!(f, "-{}", number(upper))?;
+        }
+        if let Some(unit) = self.unit {
+            let label = unit.label(self.upper.unwrap_or(self.amount));
+            // "200g" and "250ml" are written without a space, like TheMealDB does.
+            if matches!(unit, Unit::Gram | Unit::Kilogram | Unit::Millilitre) {
+                write!(f, "{}", label)?;
+            } else {
+                write!(f, " {}", label)?;
+            }
+        }
+        if !self.rest.is_empty() {
+            write!(f, " {}", self.rest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Rescales a raw measure by `factor` and converts it to `system`. Measures the parser can't
+/// read are returned as they are.
+pub fn adjust_measure(raw: &str, factor: f64, system: UnitSystem) -> String {
+    if factor == 1.0 && system == UnitSystem::Original {
+        return raw.to_string();
+    }
+    match Measure::parse(raw) {
+        Some(measure) => measure.scaled(factor).converted(system).to_string(),
+        None => raw.to_string(),
+    }
+}
+
+fn vulgar_fraction(c: char) -> Option<f64> {
+    Some(match c {
+        '½' => 0.5,
+        '⅓' => 1.0 / 3.0,
+        '⅔' => 2.0 / 3.0,
+        '¼' => 0.25,
+        '¾' => 0.75,
+        '⅕' => 0.2,
+        '⅙' => 1.0 / 6.0,
+        '⅛' => 0.125,
+        '⅜' => 0.375,
+        '⅝' => 0.625,
+        '⅞' => 0.875,
+        _ => return None,
+    })
+}
+
+fn parse_vulgar(s: &str) -> Option<(f64, &str)> {
+    let c = s.chars().next()?;
+    vulgar_fraction(c).map(|value| (value, &s[c.len_utf8()..]))
+}
+
+fn parse_simple_fraction(s: &str) -> Option<(f64, &str)> {
+    let (numerator, rest) = parse_digits(s)?;
+    let rest = rest.strip_prefix('/')?;
+    let (denominator, rest) = parse_digits(rest)?;
+    (denominator > 0.0).then(|| (numerator / denominator, rest))
+}
+
+fn parse_digits(s: &str) -> Option<(f64, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+fn parse_number(s: &str) -> Option<(f64, &str)> {
+    if let Some(found) = parse_vulgar(s).or_else(|| parse_simple_fraction(s)) {
+        return Some(found);
+    }
+
+    let end = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let whole: f64 = s[..end].parse().ok()?;
+    let rest = &s[end..];
+    if let Some((fraction, after)) = parse_vulgar(rest) {
+        return Some((whole + fraction, after));
+    }
+
+    // A mixed number like "1 1/2" or "1 ½".
+    let spaced = rest.trim_start();
+    if spaced.len() < rest.len()
+        && let Some((fraction, after)) = parse_vulgar(spaced).or_else(|| parse_simple_fraction(spaced))
+        && fraction < 1.0
+    {
+        return Some((whole + fraction, after));
+    }
+    Some((whole, rest))
+}
+
+/// A leading unit name that isn't the start of a longer word ("l" in "large" isn't litres).
+fn parse_unit(s: &str) -> Option<(Unit, &str)> {
+    UNIT_NAMES.iter().find_map(|(name, unit)| {
+        let candidate = s.get(..name.len())?;
+        if !candidate.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let rest = &s[name.len()..];
+        if rest.chars().next().is_some_and(char::is_alphabetic) {
+            return None;
+        }
+        Some((*unit, rest.strip_prefix('.').unwrap_or(rest)))
+    })
+}
+
+/// Metric amounts: whole numbers from 10 up, otherwise up to two decimals.
+fn format_decimal(value: f64) -> String {
+    if value >= 10.0 {
+        return format!("{}", value.round() as i64);
+    }
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Kitchen amounts: the nearest eighth, third or quarter ("1 1/2", "2/3"); whole numbers from 10 up.
+fn format_fraction(value: f64) -> String {
+    if value >= 10.0 {
+        return format!("{}", value.round() as i64);
+    }
+    const FRACTIONS: &[(f64, &str)] = &[
+        (0.0, ""),
+        (0.125, "1/8"),
+        (0.25, "1/4"),
+        (1.0 / 3.0, "1/3"),
+        (0.5, "1/2"),
+        (2.0 / 3.0, "2/3"),
+        (0.75, "3/4"),
+        (1.0, ""),
+    ];
+    let mut whole = value.floor();
+    let part = value - whole;
+    let (nearest, text) = FRACTIONS
+        .iter()
+        .min_by(|a, b| (a.0 - part).abs().total_cmp(&(b.0 - part).abs()))
+        .copied()
+        .unwrap_or((0.0, ""));
+    if nearest == 1.0 {
+        whole += 1.0;
+    }
+
+    match (whole as i64, text) {
+        // Too small to round to an eighth, but still something.
+        (0, "") if value > 0.0 => "1/8".to_string(),
+        (0, "") => "0".to_string(),
+        (0, fraction) => fraction.to_string(),
+        (whole, "") => whole.to_string(),
+        (whole, fraction) => format!("{} {}", whole, fraction),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(raw: &str) -> (f64, Option<f64>, Option<Unit>, String) {
+        let measure = Measure::parse(raw).unwrap_or_else(|| panic!("{:?} should parse", raw));
+        (measure.amount, measure.upper, measure.unit, measure.rest)
+    }
+
+    #[test]
+    fn parses_amounts_ranges_and_units() {
+        let cases = [
+            ("1 1/2 cups", 1.5, None, Some(Unit::Cup), ""),
+            ("½ tsp", 0.5, None, Some(Unit::Teaspoon), ""),
+            ("1½ lb", 1.5, None, Some(Unit::Pound), ""),
+            ("3/4 cup milk", 0.75, None, Some(Unit::Cup), "milk"),
+            ("2-3 tbs", 2.0, Some(3.0), Some(Unit::Tablespoon), ""),
+            ("2 to 3", 2.0, Some(3.0), None, ""),
+            ("200g", 200.0, None, Some(Unit::Gram), ""),
+            ("1.5 kg", 1.5, None, Some(Unit::Kilogram), ""),
+            ("2 tbsp. oil", 2.0, None, Some(Unit::Tablespoon), "oil"),
+        ];
+        for (raw, amount, upper, unit, rest) in cases {
+            assert_eq!(parsed(raw), (amount, upper, unit, rest.to_string()), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn words_starting_with_a_unit_are_not_units() {
+        assert_eq!(parsed("2 garlic"), (2.0, None, None, "garlic".to_string()));
+        assert_eq!(parsed("1 large"), (1.0, None, None, "large".to_string()));
+        assert_eq!(parsed("3 leaves"), (3.0, None, None, "leaves".to_string()));
+    }
+
+    #[test]
+    fn measures_without_an_amount_do_not_parse() {
+        for raw in ["To taste", "pinch", "", "Garnish"] {
+            assert_eq!(Measure::parse(raw), None, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn scales_measures() {
+        let cases = [
+            ("1 1/2 cups", 1.5, "2 1/4 cups"),
+            ("2 garlic", 2.0, "4 garlic"),
+            ("2-3 tbs", 2.0, "4-6 tbsp"),
+            ("200g", 0.5, "100g"),
+            ("1 cup", 0.25, "1/4 cup"),
+            ("To taste", 2.0, "To taste"),
+        ];
+        for (raw, factor, expected) in cases {
+            assert_eq!(adjust_measure(raw, factor, UnitSystem::Original), expected, "{:?} x {}", raw, factor);
+        }
+    }
+
+    #[test]
+    fn rounds_kitchen_fractions() {
+        let cases = [
+            (0.0, "0"),
+            (0.05, "1/8"),
+            (0.3, "1/3"),
+            (0.5, "1/2"),
+            (1.7, "1 2/3"),
+            (2.8, "2 3/4"),
+            (1.95, "2"),
+            (12.4, "12"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(format_fraction(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn rounds_metric_decimals() {
+        assert_eq!(format_decimal(1.25), "1.25");
+        assert_eq!(format_decimal(1.5), "1.5");
+        assert_eq!(format_decimal(2.0), "2");
+        assert_eq!(format_decimal(12.6), "13");
+    }
+
+    #[test]
+    fn converts_to_metric() {
+        let cases = [
+            ("1½ lb", "680g"),
+            ("2 to 3 cups", "480-720ml"),
+            ("5 cups", "1.2 litres"),
+            ("8 oz", "227g"),
+            ("1 tbsp", "1 tbsp"),
+            ("2 garlic", "2 garlic"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(adjust_measure(raw, 1.0, UnitSystem::Metric), expected, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn converts_to_us_units() {
+        let cases = [
+            ("200g", "7 oz"),
+            ("1kg", "2 1/4 lb"),
+            ("500ml", "2 1/8 cups"),
+            ("30ml", "2 tbsp"),
+            ("1 tsp", "1 tsp"),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(adjust_measure(raw, 1.0, UnitSystem::Imperial), expected, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn combines_measures_of_the_same_kind() {
+        let combined = |a: &str, b: &str| {
+            let (a, b) = (Measure::parse(a).unwrap(), Measure::parse(b).unwrap());
+            a.combined(&b).map(|total| total.to_string())
+        };
+        assert_eq!(combined("250g", "400g").as_deref(), Some("650g"));
+        assert_eq!(combined("800g", "400g").as_deref(), Some("1.2kg"));
+        assert_eq!(combined("1 cup", "2 tbs").as_deref(), Some("1 1/8 cups"));
+        assert_eq!(combined("2-3 cloves", "1 cloves").as_deref(), Some("3-4 cloves"));
+        assert_eq!(combined("200g", "1 cup"), None);
+        assert_eq!(combined("2 cloves", "2 slices"), None);
+        assert_eq!(combined("2 cloves", "100g"), None);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod cache;
 pub mod commands;
+pub mod measure;
 pub mod models;
+pub mod provider;
+pub mod shopping;
 pub mod utils;
\ No newline at end of file
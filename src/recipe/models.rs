@@ -8,13 +8,27 @@ pub struct MealListResponse {
     pub meals: Option<Vec<MealMin>>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct MealMin {
     #[serde(rename = "idMeal")]
     pub id: String,
+    #[serde(rename = "strMeal")]
+    pub name: Option<String>,
+    #[serde(rename = "strMealThumb")]
+    pub thumbnail: Option<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl From<&Meal> for MealMin {
+    fn from(meal: &Meal) -> Self {
+        Self {
+            id: meal.id.clone().unwrap_or_default(),
+            name: Some(meal.name.clone()),
+            thumbnail: meal.thumbnail.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Meal {
     #[serde(rename = "idMeal")]
     pub id: Option<String>,
@@ -31,6 +45,10 @@ pub struct Meal {
 }
 
 impl Meal {
+    pub(crate) fn area(&self) -> Option<&str> {
+        self.extra.get("strArea").and_then(|area| area.as_deref()).filter(|area| !area.trim().is_empty())
+    }
+
     pub(crate) fn get_ingredients(&self) -> Vec<(String, String)> {
         let mut ingredients = Vec::new();
         for i in 1..=20 {
@@ -73,4 +91,206 @@ pub enum MealCategory {
     #[name = "Vegetarian"] Vegetarian,
     #[name = "Breakfast"] Breakfast,
     #[name = "Goat"] Goat,
-}
\ No newline at end of file
+}
+
+/// TheMealDB's cuisines, offered as autocomplete suggestions for `by-area`. There are more than
+/// Discord's 25 choices, so unlike [`MealCategory`] any text is accepted.
+pub const MEAL_AREAS: &[&str] = &[
+    "American", "British", "Canadian", "Chinese", "Croatian", "Dutch", "Egyptian", "Filipino",
+    "French", "Greek", "Indian", "Irish", "Italian", "Jamaican", "Japanese", "Kenyan", "Malaysian",
+    "Mexican", "Moroccan", "Polish", "Portuguese", "Russian", "Spanish", "Thai", "Tunisian",
+    "Turkish", "Ukrainian", "Uruguayan", "Vietnamese",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, poise::ChoiceParameter)]
+pub enum UnitSystem {
+    #[default]
+    #[name = "As written"] Original,
+    #[name = "Metric (g, ml)"] Metric,
+    #[name = "US (oz, cups)"] Imperial,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, poise::ChoiceParameter)]
+pub enum ShoppingDelivery {
+    #[default]
+    #[name = "Here (only you can see it)"] Here,
+    #[name = "Direct message"] Dm,
+    #[name = "Text file"] File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum ExclusionKind {
+    #[name = "Ingredient (e.g. peanut, pork)"] Ingredient,
+    #[name = "Category (e.g. Pork, Seafood)"] Category,
+}
+
+impl ExclusionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ingredient => "ingredient",
+            Self::Category => "category",
+        }
+    }
+}
+
+/// Whose exclusions: a member's apply to the recipes they ask for, a server's to its daily recipe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExclusionScope {
+    User,
+    Guild,
+}
+
+impl ExclusionScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Guild => "guild",
+        }
+    }
+}
+
+/// Ingredients and categories to keep out of suggestions, all lowercase.
+#[derive(Debug, Clone, Default)]
+pub struct RecipeExclusions {
+    pub ingredients: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+impl RecipeExclusions {
+    pub fn from_rows(rows: Vec<(String, String)>) -> Self {
+        let mut exclusions = Self::default();
+        for (kind, value) in rows {
+            match kind.as_str() {
+                "ingredient" => exclusions.ingredients.push(value),
+                "category" => exclusions.categories.push(value),
+                _ => {}
+            }
+        }
+        exclusions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ingredients.is_empty() && self.categories.is_empty()
+    }
+
+    /// Whether `meal` avoids every excluded category and ingredient. Ingredients match anywhere in
+    /// a word, so "nut" rules out "Walnuts" and "milk" rules out "Buttermilk"; a missed allergen is
+    /// worse than a skipped recipe. Only the words in [`FALSE_FRIENDS`] are let through.
+    pub fn allows(&self, meal: &Meal) -> bool {
+        if let Some(category) = meal.category.as_deref()
+            && self.categories.iter().any(|c| c.eq_ignore_ascii_case(category.trim()))
+        {
+            return false;
+        }
+        !meal.get_ingredients().iter().any(|(ingredient, _)| {
+            let words = words(ingredient);
+            self.ingredients.iter().any(|excluded| mentions(&words, &self::words(excluded)))
+        })
+    }
+}
+
+/// Words that contain an excluded ingredient without being made of it, e.g. Graham crackers
+/// have no ham in them.
+const FALSE_FRIENDS: &[(&str, &[&str])] = &[
+    ("ham", &["graham", "champagne"]),
+    ("egg", &["eggplant"]),
+    ("pea", &["peanut", "pear", "pearl"]),
+    ("nut", &["nutmeg", "butternut"]),
+    ("corn", &["peppercorn"]),
+    ("oat", &["goat"]),
+    ("tea", &["steak"]),
+    ("rum", &["drumstick"]),
+];
+
+/// Lowercase, singular words of an ingredient name ("Anchovies" becomes "anchovy").
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| singular(&word.to_lowercase()))
+        .collect()
+}
+
+fn singular(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if let Some(stem) = word.strip_suffix("oes") {
+        format!("{}o", stem)
+    } else if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") {
+        word[..word.len() - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+/// Whether `ingredient` mentions `excluded`: a single word anywhere inside one of its words, more
+/// words as a run of text ("peanut butter" in "Smooth Peanut Butter").
+fn mentions(ingredient: &[String], excluded: &[String]) -> bool {
+    match excluded {
+        [] => false,
+        [word] => ingredient.iter().any(|candidate| {
+            candidate.contains(word.as_str())
+                && !FALSE_FRIENDS.iter().any(|(term, friends)| term == word && friends.contains(&candidate.as_str()))
+        }),
+        _ => ingredient.join(" ").contains(&excluded.join(" ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meal_with(ingredients: &[&str]) -> Meal {
+        let mut extra = std::collections::HashMap::new();
+        for (idx, ingredient) in ingredients.iter().enumerate() {
+            extra.insert(format!("strIngredient{}", idx + 1), Some(ingredient.to_string()));
+            extra.insert(format!("strMeasure{}", idx + 1), Some("1".to_string()));
+        }
+        Meal {
+            id: Some("1".to_string()),
+            name: "Test".to_string(),
+            instructions: String::new(),
+            category: Some("Dessert".to_string()),
+            thumbnail: None,
+            extra,
+        }
+    }
+
+    fn excluding(ingredients: &[&str]) -> RecipeExclusions {
+        RecipeExclusions { ingredients: ingredients.iter().map(|i| i.to_string()).collect(), categories: Vec::new() }
+    }
+
+    #[test]
+    fn excluded_ingredient_rules_out_longer_names() {
+        assert!(!excluding(&["peanut"]).allows(&meal_with(&["Flour", "Peanut Butter"])));
+        assert!(!excluding(&["peanut butter"]).allows(&meal_with(&["Smooth Peanut Butter"])));
+        assert!(!excluding(&["egg"]).allows(&meal_with(&["Eggs"])));
+        assert!(!excluding(&["egg"]).allows(&meal_with(&["Egg White"])));
+        assert!(!excluding(&["tomato"]).allows(&meal_with(&["Chopped Tomatoes"])));
+    }
+
+    #[test]
+    fn excluded_ingredient_rules_out_compound_words() {
+        assert!(!excluding(&["nut"]).allows(&meal_with(&["Walnuts"])));
+        assert!(!excluding(&["nut"]).allows(&meal_with(&["Peanuts"])));
+        assert!(!excluding(&["nut"]).allows(&meal_with(&["Coconut Milk"])));
+        assert!(!excluding(&["milk"]).allows(&meal_with(&["Buttermilk"])));
+        assert!(!excluding(&["fish"]).allows(&meal_with(&["Monkfish"])));
+        assert!(!excluding(&["anchovy"]).allows(&meal_with(&["Anchovies"])));
+        assert!(!excluding(&["anchovies"]).allows(&meal_with(&["Anchovy Fillet"])));
+    }
+
+    #[test]
+    fn excluded_ingredient_lets_false_friends_through() {
+        assert!(excluding(&["ham"]).allows(&meal_with(&["Graham crackers", "Shallots"])));
+        assert!(excluding(&["egg"]).allows(&meal_with(&["Eggplant"])));
+        assert!(excluding(&["nut"]).allows(&meal_with(&["Nutmeg"])));
+        assert!(excluding(&["peanut butter"]).allows(&meal_with(&["Peanut Oil"])));
+    }
+
+    #[test]
+    fn excluded_category_matches_case_insensitively() {
+        let exclusions = RecipeExclusions { ingredients: Vec::new(), categories: vec!["dessert".to_string()] };
+        assert!(!exclusions.allows(&meal_with(&["Sugar"])));
+        assert!(RecipeExclusions::default().allows(&meal_with(&["Sugar"])));
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use anyhow::Context as _;
+use rand::seq::IndexedRandom;
+use crate::recipe::cache::Cached;
+use crate::recipe::models::{Meal, MealListResponse, MealMin, MealsResponse, RecipeExclusions};
+use crate::recipe::utils::mealdb_base_url;
+use crate::shared::types::Error;
+
+/// A source of recipes. Lookups by category or ingredient return summaries; fetch the full
+/// recipe with [`RecipeProvider::by_id`].
+#[async_trait::async_trait]
+pub trait RecipeProvider: Send + Sync {
+    async fn random(&self) -> Result<Option<Meal>, Error>;
+    async fn by_category(&self, category: &str) -> Result<Vec<MealMin>, Error>;
+    async fn by_ingredient(&self, ingredient: &str) -> Result<Vec<MealMin>, Error>;
+    async fn by_area(&self, area: &str) -> Result<Vec<MealMin>, Error>;
+    async fn by_id(&self, id: &str) -> Result<Option<Meal>, Error>;
+    async fn search(&self, name: &str) -> Result<Vec<Meal>, Error>;
+
+    /// A search for autocomplete. Every keystroke is a new query, so caching providers don't
+    /// store these.
+    async fn suggest(&self, partial: &str) -> Result<Vec<Meal>, Error> {
+        self.search(partial).await
+    }
+}
+
+/// The provider used by commands and schedulers, picked from the environment on first use:
+/// RECIPE_SOURCE=local serves LOCAL_RECIPES_FILE only; otherwise TheMealDB is used through the
+/// SQLite cache, falling back to LOCAL_RECIPES_FILE (when set) for anything it can't answer.
+pub fn recipe_provider() -> Arc<dyn RecipeProvider> {
+    static PROVIDER: OnceLock<Arc<dyn RecipeProvider>> = OnceLock::new();
+    PROVIDER.get_or_init(build_provider).clone()
+}
+
+fn build_provider() -> Arc<dyn RecipeProvider> {
+    let local = std::env::var("LOCAL_RECIPES_FILE")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+        .and_then(|path| match LocalRecipes::from_file(Path::new(path.trim())) {
+            Ok(local) => Some(local),
+            Err(e) => {
+                tracing::error!("Failed to load local recipes: {}", e);
+                None
+            }
+        });
+    let source = std::env::var("RECIPE_SOURCE").unwrap_or_default();
+
+    match (source.trim().eq_ignore_ascii_case("local"), local) {
+        (true, Some(local)) => Arc::new(local),
+        (true, None) => {
+            tracing::warn!("RECIPE_SOURCE=local needs a readable LOCAL_RECIPES_FILE; using TheMealDB instead");
+            Arc::new(Cached::new(MealDb::new()))
+        }
+        (false, Some(local)) => Arc::new(WithFallback { primary: Cached::new(MealDb::new()), fallback: local }),
+        (false, None) => Arc::new(Cached::new(MealDb::new())),
+    }
+}
+
+pub struct MealDb {
+    client: reqwest::Client,
+}
+
+impl MealDb {
+    pub fn new() -> Self {
+        Self { client: reqwest::Client::new() }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<T, Error> {
+        let url = format!("{}{}", mealdb_base_url(), endpoint);
+        let response = self
+            .client
+            .get(&url)
+            .query(query)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to fetch {}: {}", endpoint, e))?;
+        Ok(response.json().await.map_err(|e| format!("Failed to parse {} JSON: {}", endpoint, e))?)
+    }
+}
+
+impl Default for MealDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl RecipeProvider for MealDb {
+    async fn random(&self) -> Result<Option<Meal>, Error> {
+        let response: MealsResponse = self.get("random.php", &[]).await?;
+        Ok(response.meals.and_then(|meals| meals.into_iter().next()))
+    }
+
+    async fn by_category(&self, category: &str) -> Result<Vec<MealMin>, Error> {
+        let response: MealListResponse = self.get("filter.php", &[("c", category)]).await?;
+        Ok(response.meals.unwrap_or_default())
+    }
+
+    async fn by_ingredient(&self, ingredient: &str) -> Result<Vec<MealMin>, Error> {
+        let response: MealListResponse = self.get("filter.php", &[("i", ingredient)]).await?;
+        Ok(response.meals.unwrap_or_default())
+    }
+
+    async fn by_area(&self, area: &str) -> Result<Vec<MealMin>, Error> {
+        let response: MealListResponse = self.get("filter.php", &[("a", area)]).await?;
+        Ok(response.meals.unwrap_or_default())
+    }
+
+    async fn by_id(&self, id: &str) -> Result<Option<Meal>, Error> {
+        let response: MealsResponse = self.get("lookup.php", &[("i", id)]).await?;
+        Ok(response.meals.and_then(|meals| meals.into_iter().next()))
+    }
+
+    async fn search(&self, name: &str) -> Result<Vec<Meal>, Error> {
+        let response: MealsResponse = self.get("search.php", &[("s", name)]).await?;
+        Ok(response.meals.unwrap_or_default())
+    }
+}
+
+pub struct LocalRecipes {
+    meals: Vec<Meal>,
+}
+
+impl LocalRecipes {
+    pub fn new(meals: Vec<Meal>) -> Self {
+        Self { meals }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let response: MealsResponse =
+            serde_json::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+        Ok(Self::new(response.meals.unwrap_or_default()))
+    }
+
+    fn summaries<'a>(&'a self, keep: impl Fn(&Meal) -> bool + 'a) -> Vec<MealMin> {
+        self.meals.iter().filter(|meal| keep(meal)).map(MealMin::from).collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl RecipeProvider for LocalRecipes {
+    async fn random(&self) -> Result<Option<Meal>, Error> {
+        Ok(self.meals.choose(&mut rand::rng()).cloned())
+    }
+
+    async fn by_category(&self, category: &str) -> Result<Vec<MealMin>, Error> {
+        Ok(self.summaries(|meal| meal.category.as_deref().is_some_and(|c| c.eq_ignore_ascii_case(category))))
+    }
+
+    async fn by_ingredient(&self, ingredient: &str) -> Result<Vec<MealMin>, Error> {
+        // TheMealDB accepts underscores for spaces ("chicken_breast").
+        let ingredient = ingredient.trim().replace('_', " ");
+        Ok(self.summaries(move |meal| {
+            meal.get_ingredients().iter().any(|(name, _)| name.trim().eq_ignore_ascii_case(&ingredient))
+        }))
+    }
+
+    async fn by_area(&self, area: &str) -> Result<Vec<MealMin>, Error> {
+        let area = area.trim();
+        Ok(self.summaries(move |meal| meal.area().is_some_and(|a| a.eq_ignore_ascii_case(area))))
+    }
+
+    async fn by_id(&self, id: &str) -> Result<Option<Meal>, Error> {
+        Ok(self.meals.iter().find(|meal| meal.id.as_deref() == Some(id)).cloned())
+    }
+
+    async fn search(&self, name: &str) -> Result<Vec<Meal>, Error> {
+        let name = name.trim().to_lowercase();
+        Ok(self.meals.iter().filter(|meal| meal.name.to_lowercase().contains(&name)).cloned().collect())
+    }
+}
+
+pub struct WithFallback<P, F> {
+    pub primary: P,
+    pub fallback: F,
+}
+
+#[async_trait::async_trait]
+impl<P: RecipeProvider, F: RecipeProvider> RecipeProvider for WithFallback<P, F> {
+    async fn random(&self) -> Result<Option<Meal>, Error> {
+        match self.primary.random().await {
+            Ok(meal) => Ok(meal),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.random().await
+            }
+        }
+    }
+
+    async fn by_category(&self, category: &str) -> Result<Vec<MealMin>, Error> {
+        match self.primary.by_category(category).await {
+            Ok(meals) => Ok(meals),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.by_category(category).await
+            }
+        }
+    }
+
+    async fn by_ingredient(&self, ingredient: &str) -> Result<Vec<MealMin>, Error> {
+        match self.primary.by_ingredient(ingredient).await {
+            Ok(meals) => Ok(meals),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.by_ingredient(ingredient).await
+            }
+        }
+    }
+
+    async fn by_area(&self, area: &str) -> Result<Vec<MealMin>, Error> {
+        match self.primary.by_area(area).await {
+            Ok(meals) => Ok(meals),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.by_area(area).await
+            }
+        }
+    }
+
+    async fn by_id(&self, id: &str) -> Result<Option<Meal>, Error> {
+        match self.primary.by_id(id).await {
+            Ok(meal) => Ok(meal),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.by_id(id).await
+            }
+        }
+    }
+
+    async fn search(&self, name: &str) -> Result<Vec<Meal>, Error> {
+        match self.primary.search(name).await {
+            Ok(meals) => Ok(meals),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.search(name).await
+            }
+        }
+    }
+
+    async fn suggest(&self, partial: &str) -> Result<Vec<Meal>, Error> {
+        match self.primary.suggest(partial).await {
+            Ok(meals) => Ok(meals),
+            Err(e) => {
+                note_failure(&e);
+                self.fallback.suggest(partial).await
+            }
+        }
+    }
+}
+
+fn note_failure(e: &Error) {
+    tracing::warn!("Recipe provider failed, using the fallback: {}", e);
+}
+
+const MAX_REROLLS: usize = 10;
+
+pub async fn random_allowed_meal(provider: &dyn RecipeProvider, exclusions: &RecipeExclusions) -> Result<Option<Meal>, Error> {
+    for _ in 0..MAX_REROLLS {
+        match provider.random().await? {
+            Some(meal) if exclusions.allows(&meal) => return Ok(Some(meal)),
+            Some(_) => continue,
+            None => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+/// Picks one of `meals` at random and fetches the full recipe, rerolling (without repeats) up to
+/// [`MAX_REROLLS`] times while `exclusions` rule it out.
+pub async fn random_meal_from(
+    provider: &dyn RecipeProvider,
+    meals: &[MealMin],
+    exclusions: &RecipeExclusions,
+) -> Result<Option<Meal>, Error> {
+    let ids: Vec<String> = meals
+        .choose_multiple(&mut rand::rng(), MAX_REROLLS)
+        .map(|meal| meal.id.clone())
+        .collect();
+    for id in ids {
+        if let Some(meal) = provider.by_id(&id).await?
+            && exclusions.allows(&meal)
+        {
+            return Ok(Some(meal));
+        }
+    }
+    Ok(None)
+}
+
+/// Recipes that use every one of `ingredients` and, when given, come from `area`: the
+/// intersection of the individual lookups, in the order of the first one.
+pub async fn meals_matching(
+    provider: &dyn RecipeProvider,
+    ingredients: &[String],
+    area: Option<&str>,
+) -> Result<Vec<MealMin>, Error> {
+    let mut sets = Vec::new();
+    for ingredient in ingredients {
+        sets.push(provider.by_ingredient(ingredient).await?);
+    }
+    if let Some(area) = area {
+        sets.push(provider.by_area(area).await?);
+    }
+
+    let Some((first, rest)) = sets.split_first() else { return Ok(Vec::new()) };
+    let rest: Vec<HashSet<&str>> =
+        rest.iter().map(|meals| meals.iter().map(|meal| meal.id.as_str()).collect()).collect();
+    Ok(first
+        .iter()
+        .filter(|meal| rest.iter().all(|ids| ids.contains(meal.id.as_str())))
+        .cloned()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> LocalRecipes {
+        LocalRecipes::from_file(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/recipes.json")))
+            .expect("fixture should load")
+    }
+
+    fn ids(meals: &[MealMin]) -> Vec<&str> {
+        meals.iter().map(|meal| meal.id.as_str()).collect()
+    }
+
+    /// A provider whose every request fails, like TheMealDB while it is down.
+    struct Unavailable;
+
+    #[async_trait::async_trait]
+    impl RecipeProvider for Unavailable {
+        async fn random(&self) -> Result<Option<Meal>, Error> {
+            Err("unavailable".into())
+        }
+        async fn by_category(&self, _category: &str) -> Result<Vec<MealMin>, Error> {
+            Err("unavailable".into())
+        }
+        async fn by_ingredient(&self, _ingredient: &str) -> Result<Vec<MealMin>, Error> {
+            Err("unavailable".into())
+        }
+        async fn by_area(&self, _area: &str) -> Result<Vec<MealMin>, Error> {
+            Err("unavailable".into())
+        }
+        async fn by_id(&self, _id: &str) -> Result<Option<Meal>, Error> {
+            Err("unavailable".into())
+        }
+        async fn search(&self, _name: &str) -> Result<Vec<Meal>, Error> {
+            Err("unavailable".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn local_search_matches_part_of_the_name() {
+        let recipes = fixture();
+        let names: Vec<String> = recipes.search("mushroom").await.unwrap().into_iter().map(|meal| meal.name).collect();
+        assert_eq!(names, vec!["Creamy Mushroom Pasta", "Mushroom Risotto"]);
+        assert!(recipes.search("lasagne").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn local_filters_ignore_case() {
+        let recipes = fixture();
+        assert_eq!(ids(&recipes.by_category("pasta").await.unwrap()), vec!["local-1"]);
+        assert_eq!(ids(&recipes.by_area("ITALIAN").await.unwrap()), vec!["local-1", "local-2"]);
+        assert_eq!(ids(&recipes.by_ingredient("mushrooms").await.unwrap()), vec!["local-1", "local-2"]);
+        assert_eq!(ids(&recipes.by_ingredient("chicken_breast").await.unwrap()), vec!["local-3"]);
+        assert!(recipes.by_category("Seafood").await.unwrap().is_empty());
+        assert!(recipes.by_area("Thai").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn local_by_id_finds_the_full_recipe() {
+        let recipes = fixture();
+        let meal = recipes.by_id("local-3").await.unwrap().expect("local-3 exists");
+        assert_eq!(meal.name, "Chicken Fajitas");
+        assert_eq!(meal.area(), Some("Mexican"));
+        assert!(recipes.by_id("missing").await.unwrap().is_none());
+        assert!(recipes.random().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn fallback_answers_when_the_primary_fails() {
+        let provider = WithFallback { primary: Unavailable, fallback: fixture() };
+        assert_eq!(ids(&provider.by_area("Mexican").await.unwrap()), vec!["local-3"]);
+        assert_eq!(ids(&provider.by_category("Vegetarian").await.unwrap()), vec!["local-2"]);
+        assert_eq!(ids(&provider.by_ingredient("Onion").await.unwrap()), vec!["local-2", "local-3"]);
+        assert_eq!(provider.search("fajitas").await.unwrap().len(), 1);
+        assert!(provider.by_id("local-1").await.unwrap().is_some());
+        assert!(provider.random().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn fallback_is_not_used_when_the_primary_answers() {
+        let provider = WithFallback { primary: LocalRecipes::new(Vec::new()), fallback: fixture() };
+        assert!(provider.search("mushroom").await.unwrap().is_empty());
+        assert!(provider.by_id("local-1").await.unwrap().is_none());
+
+        let provider = WithFallback { primary: Unavailable, fallback: Unavailable };
+        assert!(provider.search("mushroom").await.is_err());
+    }
+}
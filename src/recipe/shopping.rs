@@ -0,0 +1,133 @@
+use crate::recipe::measure::{adjust_measure, Measure};
+use crate::recipe::models::Meal;
+use crate::recipe::utils::RecipeView;
+use crate::shared::db::{update_shopping_list, ShoppingItemRow};
+use crate::shared::types::Error;
+
+/// Custom id prefix of the "Add to shopping list" button: `recipe:shop:<flags>:<meal id>`, with
+/// the same [`RecipeView`] flags as the page buttons so scaled recipes add scaled amounts.
+pub const SHOPPING_BUTTON_PREFIX: &str = "recipe:shop:";
+
+/// Adds `measure` to the measures already needed for an ingredient, summing it into one of the
+/// same kind when there is one ("250g" and "400g" become "650g"). Measures without an amount
+/// ("To taste") are only kept once.
+pub fn merge_measure(measures: &mut Vec<String>, measure: &str) {
+    let measure = measure.trim();
+    if measure.is_empty() {
+        return;
+    }
+
+    match Measure::parse(measure) {
+        Some(new) => {
+            for existing in measures.iter_mut() {
+                if let Some(total) = Measure::parse(existing).and_then(|old| old.combined(&new)) {
+                    *existing = total.to_string();
+                    return;
+                }
+            }
+        }
+        None if measures.iter().any(|existing| existing.eq_ignore_ascii_case(measure)) => return,
+        None => {}
+    }
+    measures.push(measure.to_string());
+}
+
+pub fn add_meal_to_shopping_list(user_id: u64, meal: &Meal, view: &RecipeView) -> Result<usize, Error> {
+    update_shopping_list(user_id as i64, |list| merge_meal(list, meal, view))
+}
+
+fn merge_meal(mut list: Vec<ShoppingItemRow>, meal: &Meal, view: &RecipeView) -> Vec<ShoppingItemRow> {
+    let mut changed: Vec<ShoppingItemRow> = Vec::new();
+
+    for (ingredient, measure) in meal.get_ingredients() {
+        let ingredient = ingredient.trim();
+        if ingredient.is_empty() {
+            continue;
+        }
+        let measure = adjust_measure(measure.trim(), view.factor(), view.units);
+
+        // The same ingredient can show up twice in one recipe, so look at pending changes first.
+        let item = match changed.iter_mut().position(|item| item.ingredient.eq_ignore_ascii_case(ingredient)) {
+            Some(idx) => &mut changed[idx],
+            None => {
+                let item = match list.iter().position(|item| item.ingredient.eq_ignore_ascii_case(ingredient)) {
+                    Some(idx) => list.swap_remove(idx),
+                    None => ShoppingItemRow { ingredient: ingredient.to_string(), measures: Vec::new(), recipes: Vec::new() },
+                };
+                changed.push(item);
+                changed.last_mut().expect("just pushed")
+            }
+        };
+        merge_measure(&mut item.measures, &measure);
+        if !item.recipes.iter().any(|recipe| recipe == &meal.name) {
+            item.recipes.push(meal.name.clone());
+        }
+    }
+    changed
+}
+
+pub fn shopping_list_lines(items: &[ShoppingItemRow]) -> Vec<String> {
+    items
+        .iter()
+        .map(|item| {
+            if item.measures.is_empty() {
+                item.ingredient.clone()
+            } else {
+                format!("{}: {}", item.ingredient, item.measures.join(" + "))
+            }
+        })
+        .collect()
+}
+
+pub fn shopping_list_recipes(items: &[ShoppingItemRow]) -> Vec<String> {
+    let mut recipes: Vec<String> = Vec::new();
+    for recipe in items.iter().flat_map(|item| &item.recipes) {
+        if !recipes.contains(recipe) {
+            recipes.push(recipe.clone());
+        }
+    }
+    recipes
+}
+
+pub fn shopping_list_text(items: &[ShoppingItemRow]) -> String {
+    let mut text = String::from("Shopping list\n\n");
+    for line in shopping_list_lines(items) {
+        text.push_str(&format!("- [ ] {}\n", line));
+    }
+    let recipes = shopping_list_recipes(items);
+    if !recipes.is_empty() {
+        text.push_str(&format!("\nFor: {}\n", recipes.join(", ")));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(measures: &[&str]) -> Vec<String> {
+        let mut out = Vec::new();
+        for measure in measures {
+            merge_measure(&mut out, measure);
+        }
+        out
+    }
+
+    #[test]
+    fn merge_measure_sums_matching_units() {
+        assert_eq!(merged(&["250g", "400g"]), vec!["650g"]);
+        assert_eq!(merged(&["1 cup", "1/2 cup"]), vec!["1 1/2 cups"]);
+        assert_eq!(merged(&["2 cloves", "1 cloves"]), vec!["3 cloves"]);
+    }
+
+    #[test]
+    fn merge_measure_keeps_different_kinds_apart() {
+        assert_eq!(merged(&["250g", "1 handful"]), vec!["250g", "1 handful"]);
+    }
+
+    #[test]
+    fn merge_measure_keeps_unmeasured_amounts_once() {
+        assert_eq!(merged(&["To taste", "to taste", "  "]), vec!["To taste"]);
+        assert_eq!(merged(&["Pinch", "200g", "Pinch"]), vec!["Pinch", "200g"]);
+    }
+}
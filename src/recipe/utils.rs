@@ -1,11 +1,11 @@
 use poise::serenity_prelude as serenity;
-use crate::recipe::models::{Meal, MealsResponse};
+use crate::recipe::measure::adjust_measure;
+use crate::recipe::models::{ExclusionScope, Meal, RecipeExclusions, UnitSystem};
+use crate::recipe::provider::recipe_provider;
+use crate::recipe::shopping::SHOPPING_BUTTON_PREFIX;
+use crate::shared::db::get_recipe_exclusions;
 use crate::shared::types::Error;
 
-pub async fn fetch_from_mealdb(url: &str) -> Result<reqwest::Response, reqwest::Error> {
-    reqwest::get(url).await?.error_for_status()
-}
-
 pub fn mealdb_base_url() -> String {
     let mut base = std::env::var("MEALDB_BASE_URL")
         .unwrap_or_else(|_| "https://www.themealdb.com/api/json/v1/1/".to_string());
@@ -15,43 +15,384 @@ pub fn mealdb_base_url() -> String {
     base
 }
 
+/// Custom id prefix of the page buttons: `recipe:page:<page>:<flags>:<meal id>`, where the flags
+/// are a [`RecipeView`], so any page can be rebuilt from the id alone.
+pub const PAGE_BUTTON_PREFIX: &str = "recipe:page:";
+/// TheMealDB doesn't say how many a recipe serves, so scaling assumes this many.
+pub const ASSUMED_SERVINGS: u32 = 4;
+const INSTRUCTIONS_PAGE_CHARS: usize = 1500;
+const MAX_FIELDS: usize = 25;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RecipeView {
+    pub daily: bool,
+    pub repeat: bool,
+    pub servings: Option<u32>,
+    pub units: UnitSystem,
+}
+
+impl RecipeView {
+    pub fn new(servings: Option<u32>, units: Option<UnitSystem>) -> Self {
+        Self { servings, units: units.unwrap_or_default(), ..Self::default() }
+    }
+
+    pub fn factor(&self) -> f64 {
+        self.servings.map_or(1.0, |servings| servings as f64 / ASSUMED_SERVINGS as f64)
+    }
+
+    /// Packs the view into a button id: `d` daily, `r` repeat, `m`/`i` metric/US units, then the
+    /// servings, e.g. `dm6`.
+    pub fn to_flags(&self) -> String {
+        let mut flags = String::new();
+        if self.daily {
+            flags.push('d');
+        }
+        if self.repeat {
+            flags.push('r');
+        }
+        match self.units {
+            UnitSystem::Original => {}
+            UnitSystem::Metric => flags.push('m'),
+            UnitSystem::Imperial => flags.push('i'),
+        }
+        if let Some(servings) = self.servings {
+            flags.push_str(&servings.to_string());
+        }
+        flags
+    }
+
+    pub fn from_flags(flags: &str) -> Self {
+        let units = if flags.contains('m') {
+            UnitSystem::Metric
+        } else if flags.contains('i') {
+            UnitSystem::Imperial
+        } else {
+            UnitSystem::Original
+        };
+        let digits: String = flags.chars().filter(char::is_ascii_digit).collect();
+        Self {
+            daily: flags.contains('d'),
+            repeat: flags.contains('r'),
+            servings: digits.parse().ok().filter(|servings| *servings > 0),
+            units,
+        }
+    }
+
+    fn note(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(servings) = self.servings.filter(|servings| *servings != ASSUMED_SERVINGS) {
+            parts.push(format!(
+                "Scaled to {} serving{} (recipes are assumed to serve {})",
+                servings,
+                if servings == 1 { "" } else { "s" },
+                ASSUMED_SERVINGS
+            ));
+        }
+        match self.units {
+            UnitSystem::Original => {}
+            UnitSystem::Metric => parts.push("metric units".to_string()),
+            UnitSystem::Imperial => parts.push("US units".to_string()),
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        let mut note = parts.join(", ");
+        if let Some(first) = note.get(..1) {
+            note = first.to_uppercase() + &note[1..];
+        }
+        Some(format!("*{}*", note))
+    }
+}
+
 pub fn format_meal(meal: &Meal, daily: bool, repeat: bool) -> serenity::CreateEmbed {
-    let ingredients = meal.get_ingredients();
-    let ingredients_list = ingredients
-        .iter()
-        .map(|(ing, mea)| format!("- {} ({})", ing, mea))
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    let daily_str = if daily { "Daily recipe: " } else { "" };
-    let repeat_str = if repeat { " (Repeat)" } else { "" };
-
-    serenity::CreateEmbed::new()
-        .title(format!("{}{}{}", daily_str, &meal.name, repeat_str))
-        .description(format!(
-            "**Ingredients:**\n{}\n\n**Instructions:**\n{}",
-            ingredients_list, &meal.instructions
-        ))
-        .color(0x00FF00)
-        .thumbnail(meal.thumbnail.clone().unwrap_or_default())
-}
-
-pub async fn get_random_meal(_http_client: &reqwest::Client) -> Result<Option<Meal>, Error> {
-    let list_url = format!("{}random.php", mealdb_base_url());
-    let response = fetch_from_mealdb(&list_url).await?;
-    let meal_resp: MealsResponse = response.json().await?;
-
-    if let Some(meals) = meal_resp.meals {
-        Ok(meals.into_iter().next())
+    recipe_page(meal, 0, &RecipeView { daily, repeat, ..RecipeView::default() })
+}
+
+pub fn recipe_page_count(meal: &Meal) -> usize {
+    1 + instruction_pages(&meal.instructions).len()
+}
+
+/// Page 0 is the overview, the rest are numbered instructions.
+pub fn recipe_page(meal: &Meal, page: usize, view: &RecipeView) -> serenity::CreateEmbed {
+    let daily_str = if view.daily { "Daily recipe: " } else { "" };
+    let repeat_str = if view.repeat { " (Repeat)" } else { "" };
+    let instructions = instruction_pages(&meal.instructions);
+    let total = 1 + instructions.len();
+    let page = page.min(total - 1);
+
+    let mut embed = serenity::CreateEmbed::new()
+        .title(truncate_chars(&format!("{}{}{}", daily_str, &meal.name, repeat_str), 256))
+        .color(0x00FF00);
+    if let Some(url) = link(meal, "strSource") {
+        embed = embed.url(url);
+    }
+    // Local recipes may have no picture; Discord rejects an empty image URL.
+    let picture = meal.thumbnail.as_deref().filter(|url| !url.trim().is_empty());
+
+    if page > 0 {
+        if let Some(url) = picture {
+            embed = embed.thumbnail(url);
+        }
+        return embed
+            .description(format!("**Instructions**\n{}", instructions[page - 1]))
+            .footer(serenity::CreateEmbedFooter::new(format!("Page {}/{} · Instructions", page + 1, total)));
+    }
+
+    if let Some(url) = picture {
+        embed = embed.image(url);
+    }
+    let mut fields = Vec::new();
+    if let Some(category) = meal.category.as_deref().filter(|c| !c.trim().is_empty()) {
+        fields.push(("Category".to_string(), category.to_string()));
+    }
+    if let Some(area) = meal.area() {
+        fields.push(("Cuisine".to_string(), area.to_string()));
+    }
+    if let Some(tags) = meal.extra.get("strTags").and_then(|tags| tags.as_deref()).filter(|tags| !tags.trim().is_empty()) {
+        fields.push(("Tags".to_string(), tags.replace(',', ", ")));
+    }
+    for (ingredient, measure) in meal.get_ingredients() {
+        let measure = adjust_measure(measure.trim(), view.factor(), view.units);
+        let measure = if measure.is_empty() { "—".to_string() } else { measure };
+        fields.push((ingredient.trim().to_string(), measure));
+    }
+    if fields.len() > MAX_FIELDS {
+        let hidden = fields.len() - (MAX_FIELDS - 1);
+        fields.truncate(MAX_FIELDS - 1);
+        fields.push(("…".to_string(), format!("and {} more", hidden)));
+    }
+    embed = embed.fields(
+        fields
+            .into_iter()
+            .map(|(name, value)| (truncate_chars(&name, 256), truncate_chars(&value, 1024), true)),
+    );
+
+    let note = view.note();
+    // Without an id the page buttons can't find the recipe again, so show what fits here.
+    if meal.id.is_none() {
+        let description = [note, Some(meal.instructions.trim().to_string())]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if !description.is_empty() {
+            embed = embed.description(truncate_chars(&description, 4000));
+        }
+        return embed;
+    }
+    if let Some(note) = note {
+        embed = embed.description(note);
+    }
+    let footer = if total > 1 { format!("Page 1/{} · Use the buttons for the instructions", total) } else { "Page 1/1".to_string() };
+    embed.footer(serenity::CreateEmbedFooter::new(footer))
+}
+
+pub fn recipe_components(meal: &Meal, page: usize, view: &RecipeView) -> Vec<serenity::CreateActionRow> {
+    let mut rows = Vec::new();
+    let total = recipe_page_count(meal);
+    if let Some(id) = meal.id.as_deref()
+        && total > 1
+    {
+        let flags = view.to_flags();
+        let button = |target: usize| format!("{}{}:{}:{}", PAGE_BUTTON_PREFIX, target, flags, id);
+        rows.push(serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new(button(page.saturating_sub(1)))
+                .label(if page == 1 { "◀ Overview" } else { "◀ Previous" })
+                .style(serenity::ButtonStyle::Secondary)
+                .disabled(page == 0),
+            serenity::CreateButton::new(button((page + 1).min(total - 1)))
+                .label(if page == 0 { "Instructions ▶" } else { "Next ▶" })
+                .style(serenity::ButtonStyle::Primary)
+                .disabled(page + 1 >= total),
+        ]));
+    }
+
+    let mut buttons = Vec::new();
+    if let Some(id) = meal.id.as_deref() {
+        buttons.push(
+            serenity::CreateButton::new(format!("{}{}:{}", SHOPPING_BUTTON_PREFIX, view.to_flags(), id))
+                .label("🛒 Add to shopping list")
+                .style(serenity::ButtonStyle::Success),
+        );
+    }
+    buttons.extend(
+        [("strSource", "📖 Source"), ("strYoutube", "▶️ YouTube")]
+            .into_iter()
+            .filter_map(|(key, label)| link(meal, key).map(|url| serenity::CreateButton::new_link(url).label(label))),
+    );
+    if !buttons.is_empty() {
+        rows.push(serenity::CreateActionRow::Buttons(buttons));
+    }
+    rows
+}
+
+fn link<'a>(meal: &'a Meal, key: &str) -> Option<&'a str> {
+    meal.extra
+        .get(key)
+        .and_then(|url| url.as_deref())
+        .map(str::trim)
+        .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+}
+
+/// Splits instructions into numbered steps and packs them into pages. TheMealDB separates steps
+/// with line breaks, sometimes with "STEP 1"-style headings or its own numbers, which are dropped.
+fn instruction_pages(instructions: &str) -> Vec<String> {
+    let steps: Vec<&str> = instructions
+        .lines()
+        .map(|line| strip_step_number(line.trim()))
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut pages = Vec::new();
+    let mut current = String::new();
+    for (idx, step) in steps.iter().enumerate() {
+        let numbered = format!("{}. {}", idx + 1, step);
+        for piece in split_at_whitespace(&numbered, INSTRUCTIONS_PAGE_CHARS) {
+            if !current.is_empty() && current.chars().count() + 2 + piece.chars().count() > INSTRUCTIONS_PAGE_CHARS {
+                pages.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push_str("\n\n");
+            }
+            current.push_str(&piece);
+        }
+    }
+    if !current.is_empty() {
+        pages.push(current);
+    }
+    pages
+}
+
+/// Removes a leading "STEP 3", "3." or "3)" from a line, leaving "" for a heading on its own
+/// line. Numbers that belong to the step ("2.5 kg beef", "2 cups of flour") are kept.
+fn strip_step_number(line: &str) -> &str {
+    let (rest, step_word) = match line.get(..4) {
+        Some(word) if word.eq_ignore_ascii_case("step") => (line[4..].trim_start(), true),
+        _ => (line, false),
+    };
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return line;
+    }
+    let after = &rest[digits..];
+    let mut chars = after.chars();
+    match chars.next() {
+        None => "",
+        Some('.' | ')' | ':') if chars.next().is_none_or(char::is_whitespace) => after[1..].trim_start(),
+        Some(_) if step_word => after.trim_start(),
+        Some(_) => line,
+    }
+}
+
+fn split_at_whitespace(text: &str, max: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let word: String = word.chars().take(max).collect();
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max {
+            pieces.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+    pieces
+}
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        text.chars().take(max - 1).collect::<String>() + "…"
     } else {
-        Ok(None)
+        text.to_string()
     }
 }
 
-pub async fn get_and_format_random_recipe(_http_client: &reqwest::Client) -> Result<Option<serenity::CreateEmbed>, Error> {
-    if let Some(meal) = get_random_meal(_http_client).await? {
+pub async fn get_random_meal() -> Result<Option<Meal>, Error> {
+    recipe_provider().random().await
+}
+
+pub async fn get_and_format_random_recipe() -> Result<Option<serenity::CreateEmbed>, Error> {
+    if let Some(meal) = get_random_meal().await? {
         Ok(Some(format_meal(&meal, true, false)))
     } else {
         Ok(None)
     }
 }
+
+/// Loads a member's or server's dietary exclusions. A failed read is logged and treated as none,
+/// so a database hiccup doesn't stop recipes from being suggested.
+pub fn load_exclusions(scope: ExclusionScope, owner_id: u64) -> RecipeExclusions {
+    match get_recipe_exclusions(scope.as_str(), owner_id as i64) {
+        Ok(rows) => RecipeExclusions::from_rows(rows),
+        Err(e) => {
+            tracing::error!("Failed to load recipe exclusions: {}", e);
+            RecipeExclusions::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_step_number_removes_step_markers() {
+        let cases = [
+            ("1. Preheat the oven.", "Preheat the oven."),
+            ("2) Chop the onions.", "Chop the onions."),
+            ("3: Fry until golden.", "Fry until golden."),
+            ("STEP 4 Serve hot.", "Serve hot."),
+            ("Step 5: Enjoy.", "Enjoy."),
+            ("STEP 6", ""),
+            ("7.", ""),
+            ("8", ""),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(strip_step_number(line), expected, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn strip_step_number_keeps_numbers_in_the_text() {
+        let cases = [
+            "2.5 kg beef, cut into chunks.",
+            "2 cups of flour, sifted.",
+            "350F oven for 20 minutes.",
+            "1:2 ratio of rice to water.",
+            "Stephanie's trick: salt the water.",
+            "Season to taste.",
+        ];
+        for line in cases {
+            assert_eq!(strip_step_number(line), line);
+        }
+    }
+
+    #[test]
+    fn instruction_pages_numbers_steps_and_drops_headings() {
+        let pages = instruction_pages("STEP 1\r\nBoil water.\r\n\r\nSTEP 2\r\n2.5 kg potatoes go in.\r\n3. Drain.");
+        assert_eq!(pages, vec!["1. Boil water.\n\n2. 2.5 kg potatoes go in.\n\n3. Drain."]);
+    }
+
+    #[test]
+    fn instruction_pages_split_long_instructions() {
+        let step = "Stir the sauce slowly. ".repeat(40);
+        let instructions = [step.trim(); 5].join("\n");
+        let pages = instruction_pages(&instructions);
+
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|page| page.chars().count() <= INSTRUCTIONS_PAGE_CHARS));
+        assert!(pages[0].starts_with("1. Stir"));
+        assert_eq!(pages.iter().filter(|page| page.contains("5. Stir")).count(), 1);
+    }
+
+    #[test]
+    fn instruction_pages_of_empty_instructions() {
+        assert!(instruction_pages("").is_empty());
+        assert!(instruction_pages("\r\n\r\n").is_empty());
+    }
+}
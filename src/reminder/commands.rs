@@ -75,7 +75,7 @@ pub async fn register(
         if private { "private" } else { "public" },
         tz_str
     );
-    if let Some(n) = &note { if !n.trim().is_empty() { msg.push_str(&format!(" Note: {}", n.trim())); } }
+    if let Some(n) = &note && !n.trim().is_empty() { msg.push_str(&format!(" Note: {}", n.trim())); }
 
     ctx.send(
         poise::CreateReply::default()
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
+
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
 
 use crate::shared::types::Error;
 
@@ -22,6 +24,65 @@ pub fn init_db() -> Result<(), Error> {
         [],
     )?;
 
+    // Tables caching TheMealDB responses: full recipes by id, and lists (filters, searches) by query
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recipe_cache_meals (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            category TEXT,
+            json TEXT NOT NULL,
+            cached_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recipe_cache_lists (
+            query TEXT PRIMARY KEY,
+            json TEXT NOT NULL,
+            cached_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    // Ingredients and categories members (scope 'user') and servers (scope 'guild') don't want
+    // suggested
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recipe_exclusions (
+            scope TEXT NOT NULL,
+            owner_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            value TEXT NOT NULL,
+            PRIMARY KEY (scope, owner_id, kind, value)
+        )",
+        [],
+    )?;
+
+    // Each member's shopping list: one row per ingredient, with the measures still needed
+    // (newline-separated, already merged where possible) and the recipes they came from
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shopping_list (
+            user_id INTEGER NOT NULL,
+            ingredient TEXT NOT NULL COLLATE NOCASE,
+            measures TEXT NOT NULL DEFAULT '',
+            recipes TEXT NOT NULL DEFAULT '',
+            added_at TEXT NOT NULL,
+            PRIMARY KEY (user_id, ingredient)
+        )",
+        [],
+    )?;
+
+    // Table for logging songs of the day per guild
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS daily_songs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id INTEGER NOT NULL,
+            track_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            sent_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
     // Table for users who opted into TTS per guild
     conn.execute(
         "CREATE TABLE IF NOT EXISTS tts_signups (
@@ -82,9 +143,164 @@ pub fn init_db() -> Result<(), Error> {
         [],
     )?;
 
+    // Index of the music library (paths are relative to MUSIC_FOLDER)
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS music_tracks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            path TEXT NOT NULL UNIQUE,
+            folder TEXT NOT NULL,
+            series TEXT NOT NULL,
+            title TEXT NOT NULL,
+            duration_secs INTEGER,
+            size INTEGER NOT NULL,
+            mtime INTEGER NOT NULL,
+            scanned_at TEXT NOT NULL,
+            artist TEXT,
+            album TEXT,
+            track_number INTEGER
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_music_tracks_folder ON music_tracks (folder)",
+        [],
+    )?;
+
+    // Tag columns were added later; force a re-read of already indexed files when they appear
+    let mut tags_added = false;
+    for (column, definition) in [("artist", "TEXT"), ("album", "TEXT"), ("track_number", "INTEGER")] {
+        tags_added |= ensure_column(&conn, "music_tracks", column, definition)?;
+    }
+    // Loudness gain (dB, relative to the ReplayGain reference) comes from tags or an EBU R128 analysis
+    tags_added |= ensure_column(&conn, "music_tracks", "gain_db", "REAL")?;
+    ensure_column(&conn, "music_tracks", "loudness_checked", "INTEGER NOT NULL DEFAULT 0")?;
+    if tags_added {
+        conn.execute("UPDATE music_tracks SET mtime = 0", [])?;
+    }
+
+    // Table for the songs listed in .m3u/.m3u8 files of the music library, keyed by the file's path
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS music_collection_tracks (
+            collection TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            track_id INTEGER NOT NULL,
+            PRIMARY KEY (collection, position)
+        )",
+        [],
+    )?;
+
+    // Table for per-guild voice playback settings
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS voice_settings (
+            guild_id INTEGER PRIMARY KEY,
+            volume INTEGER NOT NULL DEFAULT 100
+        )",
+        [],
+    )?;
+
+    // Table for songs played in voice per guild, used to avoid repeats and for statistics
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS music_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id INTEGER NOT NULL,
+            track_id INTEGER NOT NULL,
+            played_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_music_history_guild ON music_history (guild_id, played_at)",
+        [],
+    )?;
+    // Who asked for the song and through which command, for listening statistics
+    ensure_column(&conn, "music_history", "user_id", "INTEGER")?;
+    ensure_column(&conn, "music_history", "source", "TEXT")?;
+    // Seconds the song was actually heard, filled in when it ends or is skipped
+    ensure_column(&conn, "music_history", "played_secs", "INTEGER")?;
+
+    // Table for per-guild music preferences
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS music_settings (
+            guild_id INTEGER PRIMARY KEY,
+            selection_mode TEXT NOT NULL DEFAULT 'avoid_recent',
+            history_size INTEGER NOT NULL DEFAULT 25
+        )",
+        [],
+    )?;
+    ensure_column(&conn, "music_settings", "prefer_rated", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_column(&conn, "music_settings", "skip_ratio", "INTEGER NOT NULL DEFAULT 50")?;
+    ensure_column(&conn, "music_settings", "dj_role_id", "INTEGER")?;
+    ensure_column(&conn, "music_settings", "radio_idle_minutes", "INTEGER NOT NULL DEFAULT 5")?;
+
+    // Table for track ratings (1-5) and favourites per user and guild
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS music_ratings (
+            guild_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            track_id INTEGER NOT NULL,
+            rating INTEGER,
+            favorite INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (guild_id, user_id, track_id)
+        )",
+        [],
+    )?;
+
+    // Table for guess-the-song quiz scores per guild
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS music_quiz_scores (
+            guild_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            points INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (guild_id, user_id)
+        )",
+        [],
+    )?;
+
+    // Tables for user playlists of indexed tracks
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS playlists (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            guild_id INTEGER NOT NULL,
+            owner_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            shared INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            UNIQUE (guild_id, owner_id, name COLLATE NOCASE)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS playlist_tracks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            playlist_id INTEGER NOT NULL,
+            track_id INTEGER NOT NULL,
+            added_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_playlist_tracks_playlist ON playlist_tracks (playlist_id)",
+        [],
+    )?;
+
     Ok(())
 }
 
+fn ensure_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<bool, Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(1)?;
+        if name.eq_ignore_ascii_case(column) {
+            return Ok(false);
+        }
+    }
+    conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    Ok(true)
+}
+
 pub fn was_recipe_sent(recipe_id: &str) -> Result<bool, Error> {
     let path = db_path();
     let conn = Connection::open(path)?;
@@ -104,6 +320,186 @@ pub fn log_recipe_sent(recipe_id: &str, title: &str) -> Result<(), Error> {
     Ok(())
 }
 
+pub fn get_cached_meal(id: &str) -> Result<Option<(String, String)>, Error> {
+    let conn = Connection::open(db_path())?;
+    Ok(conn
+        .query_row(
+            "SELECT json, cached_at FROM recipe_cache_meals WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?)
+}
+
+pub fn cache_meal(id: &str, name: &str, category: Option<&str>, json: &str) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    let cached_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO recipe_cache_meals (id, name, category, json, cached_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(id) DO UPDATE SET
+            name = excluded.name,
+            category = excluded.category,
+            json = excluded.json,
+            cached_at = excluded.cached_at",
+        params![id, name, category, json, cached_at],
+    )?;
+    Ok(())
+}
+
+pub fn random_cached_meal(unsent: bool) -> Result<Option<String>, Error> {
+    let conn = Connection::open(db_path())?;
+    Ok(conn
+        .query_row(
+            "SELECT json FROM recipe_cache_meals
+             WHERE ?1 = 0 OR id NOT IN (SELECT recipe_id FROM daily_recipes)
+             ORDER BY RANDOM() LIMIT 1",
+            params![unsent],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+pub fn get_cached_recipe_list(query: &str) -> Result<Option<(String, String)>, Error> {
+    let conn = Connection::open(db_path())?;
+    Ok(conn
+        .query_row(
+            "SELECT json, cached_at FROM recipe_cache_lists WHERE query = ?1",
+            params![query],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?)
+}
+
+pub fn cache_recipe_list(query: &str, json: &str) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    let cached_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO recipe_cache_lists (query, json, cached_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(query) DO UPDATE SET json = excluded.json, cached_at = excluded.cached_at",
+        params![query, json, cached_at],
+    )?;
+    Ok(())
+}
+
+/// Adds an exclusion; returns `false` if it was already there. Values are stored lowercase.
+pub fn add_recipe_exclusion(scope: &str, owner_id: i64, kind: &str, value: &str) -> Result<bool, Error> {
+    let conn = Connection::open(db_path())?;
+    let added = conn.execute(
+        "INSERT OR IGNORE INTO recipe_exclusions (scope, owner_id, kind, value) VALUES (?1, ?2, ?3, ?4)",
+        params![scope, owner_id, kind, value.trim().to_lowercase()],
+    )?;
+    Ok(added > 0)
+}
+
+pub fn remove_recipe_exclusion(scope: &str, owner_id: i64, kind: &str, value: &str) -> Result<bool, Error> {
+    let conn = Connection::open(db_path())?;
+    let removed = conn.execute(
+        "DELETE FROM recipe_exclusions WHERE scope = ?1 AND owner_id = ?2 AND kind = ?3 AND value = ?4",
+        params![scope, owner_id, kind, value.trim().to_lowercase()],
+    )?;
+    Ok(removed > 0)
+}
+
+pub fn get_recipe_exclusions(scope: &str, owner_id: i64) -> Result<Vec<(String, String)>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(
+        "SELECT kind, value FROM recipe_exclusions WHERE scope = ?1 AND owner_id = ?2 ORDER BY kind, value",
+    )?;
+    let rows = stmt.query_map(params![scope, owner_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShoppingItemRow {
+    pub ingredient: String,
+    pub measures: Vec<String>,
+    pub recipes: Vec<String>,
+}
+
+fn split_lines(text: String) -> Vec<String> {
+    text.lines().map(str::to_string).filter(|line| !line.is_empty()).collect()
+}
+
+pub fn get_shopping_list(user_id: i64) -> Result<Vec<ShoppingItemRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    read_shopping_list(&conn, user_id)
+}
+
+fn read_shopping_list(conn: &Connection, user_id: i64) -> Result<Vec<ShoppingItemRow>, Error> {
+    let mut stmt = conn.prepare(
+        "SELECT ingredient, measures, recipes FROM shopping_list WHERE user_id = ?1 ORDER BY added_at, rowid",
+    )?;
+    let rows = stmt.query_map(params![user_id], |row| {
+        Ok(ShoppingItemRow {
+            ingredient: row.get(0)?,
+            measures: split_lines(row.get(1)?),
+            recipes: split_lines(row.get(2)?),
+        })
+    })?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+/// Reads a member's list, lets `update` work out the changed items and saves those, all in one
+/// transaction so concurrent additions can't overwrite each other. Existing items keep their
+/// place in the list. Returns how many items were saved.
+pub fn update_shopping_list(
+    user_id: i64,
+    update: impl FnOnce(Vec<ShoppingItemRow>) -> Vec<ShoppingItemRow>,
+) -> Result<usize, Error> {
+    let mut conn = Connection::open(db_path())?;
+    // Take the write lock before reading, not on the first write.
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let changed = update(read_shopping_list(&tx, user_id)?);
+    let added_at = Utc::now().to_rfc3339();
+    for item in &changed {
+        tx.execute(
+            "INSERT INTO shopping_list (user_id, ingredient, measures, recipes, added_at) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(user_id, ingredient) DO UPDATE SET measures = excluded.measures, recipes = excluded.recipes",
+            params![user_id, item.ingredient, item.measures.join("\n"), item.recipes.join("\n"), added_at],
+        )?;
+    }
+    tx.commit()?;
+    Ok(changed.len())
+}
+
+pub fn remove_shopping_item(user_id: i64, ingredient: &str) -> Result<bool, Error> {
+    let conn = Connection::open(db_path())?;
+    let removed = conn.execute(
+        "DELETE FROM shopping_list WHERE user_id = ?1 AND ingredient = ?2",
+        params![user_id, ingredient.trim()],
+    )?;
+    Ok(removed > 0)
+}
+
+pub fn clear_shopping_list(user_id: i64) -> Result<usize, Error> {
+    let conn = Connection::open(db_path())?;
+    Ok(conn.execute("DELETE FROM shopping_list WHERE user_id = ?1", params![user_id])?)
+}
+
+pub fn random_new_daily_song(guild_id: i64) -> Result<Option<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM music_tracks
+         WHERE id NOT IN (SELECT track_id FROM daily_songs WHERE guild_id = ?1)
+         ORDER BY RANDOM() LIMIT 1",
+        MUSIC_TRACK_COLUMNS
+    ))?;
+    let mut rows = stmt.query_map(params![guild_id], music_track_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+pub fn log_daily_song(guild_id: i64, track_id: i64, title: &str) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    let sent_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO daily_songs (guild_id, track_id, title, sent_at) VALUES (?1, ?2, ?3, ?4)",
+        params![guild_id, track_id, title, sent_at],
+    )?;
+    Ok(())
+}
+
 pub fn tts_signup(user_id: i64, guild_id: i64) -> Result<(), Error> {
     let conn = Connection::open(db_path())?;
     let signed_at = Utc::now().to_rfc3339();
@@ -261,3 +657,673 @@ pub fn get_reminders_by_time_tz(time: &str, timezone: &str) -> Result<Vec<Remind
     }
     Ok(out)
 }
+
+#[derive(Debug, Clone)]
+pub struct MusicTrackRow {
+    pub id: i64,
+    pub path: String,
+    pub folder: String,
+    pub series: String,
+    pub title: String,
+    pub duration_secs: Option<i64>,
+    pub size: i64,
+    pub mtime: i64,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<i64>,
+    pub gain_db: Option<f64>,
+}
+
+const MUSIC_TRACK_COLUMNS: &str = "id, path, folder, series, title, duration_secs, size, mtime, artist, album, track_number, gain_db";
+const JOINED_MUSIC_TRACK_COLUMNS: &str =
+    "t.id, t.path, t.folder, t.series, t.title, t.duration_secs, t.size, t.mtime, t.artist, t.album, t.track_number, t.gain_db";
+
+fn music_track_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MusicTrackRow> {
+    Ok(MusicTrackRow {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        folder: row.get(2)?,
+        series: row.get(3)?,
+        title: row.get(4)?,
+        duration_secs: row.get(5)?,
+        size: row.get(6)?,
+        mtime: row.get(7)?,
+        artist: row.get(8)?,
+        album: row.get(9)?,
+        track_number: row.get(10)?,
+        gain_db: row.get(11)?,
+    })
+}
+
+pub fn get_music_track_stamps() -> Result<HashMap<String, (i64, i64)>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare("SELECT path, size, mtime FROM music_tracks")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, (row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))
+    })?;
+    let mut out = HashMap::new();
+    for r in rows {
+        let (path, stamp) = r?;
+        out.insert(path, stamp);
+    }
+    Ok(out)
+}
+
+pub fn save_music_tracks(tracks: &[MusicTrackRow], removed: &[String]) -> Result<(), Error> {
+    let mut conn = Connection::open(db_path())?;
+    let scanned_at = Utc::now().to_rfc3339();
+    let tx = conn.transaction()?;
+    {
+        let mut upsert = tx.prepare(
+            "INSERT INTO music_tracks (path, folder, series, title, duration_secs, size, mtime, scanned_at, artist, album, track_number, gain_db, loudness_checked)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12 IS NOT NULL)
+             ON CONFLICT(path) DO UPDATE SET
+                folder = excluded.folder,
+                series = excluded.series,
+                title = excluded.title,
+                duration_secs = excluded.duration_secs,
+                size = excluded.size,
+                mtime = excluded.mtime,
+                scanned_at = excluded.scanned_at,
+                artist = excluded.artist,
+                album = excluded.album,
+                track_number = excluded.track_number,
+                gain_db = excluded.gain_db,
+                loudness_checked = excluded.loudness_checked",
+        )?;
+        for t in tracks {
+            upsert.execute(params![
+                t.path, t.folder, t.series, t.title, t.duration_secs, t.size, t.mtime, scanned_at,
+                t.artist, t.album, t.track_number, t.gain_db
+            ])?;
+        }
+
+        let mut delete = tx.prepare("DELETE FROM music_tracks WHERE path = ?1")?;
+        for path in removed {
+            delete.execute(params![path])?;
+        }
+    }
+    if !removed.is_empty() {
+        // Drop everything that still refers to the removed tracks.
+        for table in ["playlist_tracks", "music_history", "music_ratings", "daily_songs", "music_collection_tracks"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE track_id NOT IN (SELECT id FROM music_tracks)", table),
+                [],
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Replaces the indexed playlist files. Each one is its path relative to MUSIC_FOLDER and the
+/// relative paths of the songs it lists; songs that aren't in the index are left out.
+pub fn save_music_collections(collections: &[(String, Vec<String>)]) -> Result<(), Error> {
+    let mut conn = Connection::open(db_path())?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM music_collection_tracks", [])?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO music_collection_tracks (collection, position, track_id)
+             SELECT ?1, ?2, id FROM music_tracks WHERE path = ?3",
+        )?;
+        for (collection, paths) in collections {
+            for (position, path) in paths.iter().enumerate() {
+                insert.execute(params![collection, position as i64, path])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn get_tracks_without_loudness() -> Result<Vec<(i64, String)>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare("SELECT id, path FROM music_tracks WHERE loudness_checked = 0")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+/// Stores a measured gain; `None` marks a track that couldn't be analysed so it isn't retried.
+pub fn set_music_track_gain(id: i64, gain_db: Option<f64>) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "UPDATE music_tracks SET gain_db = ?2, loudness_checked = 1 WHERE id = ?1",
+        params![id, gain_db],
+    )?;
+    Ok(())
+}
+
+/// Matches tracks in the folder `?1` (relative to MUSIC_FOLDER, empty for everything) or its
+/// subfolders, or listed in the playlist file `?1`.
+const FOLDER_FILTER: &str = "(?1 = '' OR folder = ?1 OR substr(folder, 1, length(?1) + 1) = ?1 || '/'
+    OR id IN (SELECT track_id FROM music_collection_tracks WHERE collection = ?1))";
+
+pub fn random_music_track(folder: &str, max_size: Option<i64>) -> Result<Option<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM music_tracks
+         WHERE {}
+           AND (?2 IS NULL OR size <= ?2)
+         ORDER BY RANDOM() LIMIT 1",
+        MUSIC_TRACK_COLUMNS, FOLDER_FILTER
+    ))?;
+    let mut rows = stmt.query_map(params![folder, max_size], music_track_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+pub fn random_music_track_avoiding_recent(folder: &str, max_size: Option<i64>, guild_id: i64, recent: i64) -> Result<Option<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM music_tracks
+         WHERE {}
+           AND (?2 IS NULL OR size <= ?2)
+           AND id NOT IN (SELECT track_id FROM music_history WHERE guild_id = ?3 ORDER BY played_at DESC LIMIT ?4)
+         ORDER BY RANDOM() LIMIT 1",
+        MUSIC_TRACK_COLUMNS, FOLDER_FILTER
+    ))?;
+    let mut rows = stmt.query_map(params![folder, max_size, guild_id, recent], music_track_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+pub fn get_music_track_last_plays(folder: &str, max_size: Option<i64>, guild_id: i64) -> Result<Vec<(i64, Option<String>)>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.id, (SELECT MAX(h.played_at) FROM music_history h WHERE h.guild_id = ?3 AND h.track_id = t.id)
+         FROM music_tracks t
+         WHERE {}
+           AND (?2 IS NULL OR t.size <= ?2)",
+        FOLDER_FILTER
+    ))?;
+    let rows = stmt.query_map(params![folder, max_size, guild_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn log_music_play(guild_id: i64, track_id: i64, user_id: Option<i64>, source: &str) -> Result<i64, Error> {
+    let conn = Connection::open(db_path())?;
+    let played_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO music_history (guild_id, track_id, played_at, user_id, source) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![guild_id, track_id, played_at, user_id, source],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn set_music_play_secs(history_id: i64, played_secs: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "UPDATE music_history SET played_secs = ?2 WHERE id = ?1",
+        params![history_id, played_secs],
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MusicStatsRow {
+    pub plays: i64,
+    /// Seconds actually listened to in voice.
+    pub listening_secs: i64,
+    pub listeners: i64,
+    pub top_tracks: Vec<(MusicTrackRow, i64)>,
+    /// `(user_id, plays)`
+    pub top_listeners: Vec<(i64, i64)>,
+    /// `(series, plays)`
+    pub top_series: Vec<(String, i64)>,
+    /// `(source, plays)`
+    pub sources: Vec<(String, i64)>,
+}
+
+/// Matches the guild's history `?1`, optionally limited to plays since `?2` (RFC 3339) and by user `?3`.
+const HISTORY_FILTER: &str = "h.guild_id = ?1 AND (?2 IS NULL OR h.played_at >= ?2) AND (?3 IS NULL OR h.user_id = ?3)";
+
+pub fn get_music_stats(guild_id: i64, since: Option<&str>, user_id: Option<i64>, limit: i64) -> Result<MusicStatsRow, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stats = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM(h.played_secs), 0), COUNT(DISTINCT h.user_id)
+             FROM music_history h
+             WHERE {}",
+            HISTORY_FILTER
+        ),
+        params![guild_id, since, user_id],
+        |row| {
+            Ok(MusicStatsRow {
+                plays: row.get(0)?,
+                listening_secs: row.get(1)?,
+                listeners: row.get(2)?,
+                ..Default::default()
+            })
+        },
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, COUNT(*) FROM music_history h JOIN music_tracks t ON t.id = h.track_id
+         WHERE {}
+         GROUP BY t.id
+         ORDER BY COUNT(*) DESC, MAX(h.played_at) DESC
+         LIMIT ?4",
+        JOINED_MUSIC_TRACK_COLUMNS, HISTORY_FILTER
+    ))?;
+    let rows = stmt.query_map(params![guild_id, since, user_id, limit], |row| {
+        Ok((music_track_from_row(row)?, row.get(12)?))
+    })?;
+    for r in rows { stats.top_tracks.push(r?); }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT h.user_id, COUNT(*) FROM music_history h
+         WHERE {} AND h.user_id IS NOT NULL
+         GROUP BY h.user_id
+         ORDER BY COUNT(*) DESC
+         LIMIT ?4",
+        HISTORY_FILTER
+    ))?;
+    let rows = stmt.query_map(params![guild_id, since, user_id, limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    for r in rows { stats.top_listeners.push(r?); }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.series, COUNT(*) FROM music_history h JOIN music_tracks t ON t.id = h.track_id
+         WHERE {} AND t.series != ''
+         GROUP BY t.series
+         ORDER BY COUNT(*) DESC
+         LIMIT ?4",
+        HISTORY_FILTER
+    ))?;
+    let rows = stmt.query_map(params![guild_id, since, user_id, limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    for r in rows { stats.top_series.push(r?); }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT COALESCE(h.source, 'unknown'), COUNT(*) FROM music_history h
+         WHERE {}
+         GROUP BY 1
+         ORDER BY COUNT(*) DESC
+         LIMIT ?4",
+        HISTORY_FILTER
+    ))?;
+    let rows = stmt.query_map(params![guild_id, since, user_id, limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    for r in rows { stats.sources.push(r?); }
+
+    Ok(stats)
+}
+
+#[derive(Debug, Clone)]
+pub struct MusicSettingsRow {
+    pub guild_id: i64,
+    pub selection_mode: String,
+    pub history_size: i64,
+    pub prefer_rated: bool,
+    /// Share of listeners (percent) that has to vote before a song is skipped.
+    pub skip_ratio: i64,
+    pub dj_role_id: Option<i64>,
+    /// Minutes the radio keeps playing to an empty voice channel.
+    pub radio_idle_minutes: i64,
+}
+
+pub fn get_music_settings(guild_id: i64) -> Result<MusicSettingsRow, Error> {
+    let conn = Connection::open(db_path())?;
+    let row = conn.query_row(
+        "SELECT guild_id, selection_mode, history_size, prefer_rated, skip_ratio, dj_role_id, radio_idle_minutes
+         FROM music_settings WHERE guild_id = ?1",
+        params![guild_id],
+        |row| {
+            Ok(MusicSettingsRow {
+                guild_id: row.get(0)?,
+                selection_mode: row.get(1)?,
+                history_size: row.get(2)?,
+                prefer_rated: row.get::<_, i64>(3)? != 0,
+                skip_ratio: row.get(4)?,
+                dj_role_id: row.get(5)?,
+                radio_idle_minutes: row.get(6)?,
+            })
+        },
+    ).optional()?;
+    // Guilds that never changed a setting have no row; these match the column defaults.
+    Ok(row.unwrap_or(MusicSettingsRow {
+        guild_id,
+        selection_mode: "avoid_recent".to_string(),
+        history_size: 25,
+        prefer_rated: false,
+        skip_ratio: 50,
+        dj_role_id: None,
+        radio_idle_minutes: 5,
+    }))
+}
+
+pub fn set_music_selection(guild_id: i64, selection_mode: &str, history_size: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "INSERT INTO music_settings (guild_id, selection_mode, history_size) VALUES (?1, ?2, ?3)
+         ON CONFLICT(guild_id) DO UPDATE SET selection_mode = excluded.selection_mode, history_size = excluded.history_size",
+        params![guild_id, selection_mode, history_size],
+    )?;
+    Ok(())
+}
+
+pub fn set_music_prefer_rated(guild_id: i64, prefer_rated: bool) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "INSERT INTO music_settings (guild_id, prefer_rated) VALUES (?1, ?2)
+         ON CONFLICT(guild_id) DO UPDATE SET prefer_rated = excluded.prefer_rated",
+        params![guild_id, if prefer_rated { 1 } else { 0 }],
+    )?;
+    Ok(())
+}
+
+pub fn set_music_skip_ratio(guild_id: i64, skip_ratio: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "INSERT INTO music_settings (guild_id, skip_ratio) VALUES (?1, ?2)
+         ON CONFLICT(guild_id) DO UPDATE SET skip_ratio = excluded.skip_ratio",
+        params![guild_id, skip_ratio],
+    )?;
+    Ok(())
+}
+
+pub fn set_music_dj_role(guild_id: i64, dj_role_id: Option<i64>) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "INSERT INTO music_settings (guild_id, dj_role_id) VALUES (?1, ?2)
+         ON CONFLICT(guild_id) DO UPDATE SET dj_role_id = excluded.dj_role_id",
+        params![guild_id, dj_role_id],
+    )?;
+    Ok(())
+}
+
+pub fn set_music_radio_idle(guild_id: i64, radio_idle_minutes: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "INSERT INTO music_settings (guild_id, radio_idle_minutes) VALUES (?1, ?2)
+         ON CONFLICT(guild_id) DO UPDATE SET radio_idle_minutes = excluded.radio_idle_minutes",
+        params![guild_id, radio_idle_minutes],
+    )?;
+    Ok(())
+}
+
+pub fn get_recent_music_track_ids(guild_id: i64, recent: i64) -> Result<Vec<i64>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(
+        "SELECT track_id FROM music_history WHERE guild_id = ?1 ORDER BY played_at DESC LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![guild_id, recent], |row| row.get(0))?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn get_music_track(id: i64) -> Result<Option<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM music_tracks WHERE id = ?1", MUSIC_TRACK_COLUMNS))?;
+    let mut rows = stmt.query_map(params![id], music_track_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+pub fn get_music_tracks_in_folder(folder: &str) -> Result<Vec<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM music_tracks
+         WHERE {}",
+        MUSIC_TRACK_COLUMNS, FOLDER_FILTER
+    ))?;
+    let rows = stmt.query_map(params![folder], music_track_from_row)?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn get_all_music_tracks() -> Result<Vec<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM music_tracks", MUSIC_TRACK_COLUMNS))?;
+    let rows = stmt.query_map([], music_track_from_row)?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+#[derive(Debug, Clone)]
+pub struct PlaylistRow {
+    pub id: i64,
+    pub guild_id: i64,
+    pub owner_id: i64,
+    pub name: String,
+    pub shared: bool,
+    pub track_count: i64,
+}
+
+const PLAYLIST_COLUMNS: &str = "p.id, p.guild_id, p.owner_id, p.name, p.shared,
+    (SELECT COUNT(*) FROM playlist_tracks pt WHERE pt.playlist_id = p.id)";
+
+fn playlist_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<PlaylistRow> {
+    Ok(PlaylistRow {
+        id: row.get(0)?,
+        guild_id: row.get(1)?,
+        owner_id: row.get(2)?,
+        name: row.get(3)?,
+        shared: row.get::<_, i64>(4)? != 0,
+        track_count: row.get(5)?,
+    })
+}
+
+pub fn create_playlist(guild_id: i64, owner_id: i64, name: &str, shared: bool) -> Result<bool, Error> {
+    let conn = Connection::open(db_path())?;
+    let created_at = Utc::now().to_rfc3339();
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO playlists (guild_id, owner_id, name, shared, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![guild_id, owner_id, name, if shared { 1 } else { 0 }, created_at],
+    )?;
+    Ok(inserted > 0)
+}
+
+/// Finds a playlist by name that the user can see in the guild: their own first, then shared ones.
+pub fn find_playlist(guild_id: i64, user_id: i64, name: &str) -> Result<Option<PlaylistRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM playlists p
+         WHERE p.guild_id = ?1 AND p.name = ?3 COLLATE NOCASE AND (p.owner_id = ?2 OR p.shared = 1)
+         ORDER BY p.owner_id = ?2 DESC, p.created_at
+         LIMIT 1",
+        PLAYLIST_COLUMNS
+    ))?;
+    let mut rows = stmt.query_map(params![guild_id, user_id, name], playlist_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+pub fn get_visible_playlists(guild_id: i64, user_id: i64) -> Result<Vec<PlaylistRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM playlists p
+         WHERE p.guild_id = ?1 AND (p.owner_id = ?2 OR p.shared = 1)
+         ORDER BY p.owner_id = ?2 DESC, p.name COLLATE NOCASE",
+        PLAYLIST_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![guild_id, user_id], playlist_from_row)?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn add_playlist_track(playlist_id: i64, track_id: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    let added_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO playlist_tracks (playlist_id, track_id, added_at) VALUES (?1, ?2, ?3)",
+        params![playlist_id, track_id, added_at],
+    )?;
+    Ok(())
+}
+
+/// Removes the song at `position` (1-based, in the order shown by [`get_playlist_tracks`]).
+/// Returns whether a song was removed.
+pub fn remove_playlist_track(playlist_id: i64, position: i64) -> Result<bool, Error> {
+    let conn = Connection::open(db_path())?;
+    let removed = conn.execute(
+        "DELETE FROM playlist_tracks WHERE id = (
+            SELECT id FROM playlist_tracks WHERE playlist_id = ?1 ORDER BY id LIMIT 1 OFFSET ?2
+        )",
+        params![playlist_id, position - 1],
+    )?;
+    Ok(removed > 0)
+}
+
+pub fn get_playlist_tracks(playlist_id: i64) -> Result<Vec<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM playlist_tracks pt
+         JOIN music_tracks t ON t.id = pt.track_id
+         WHERE pt.playlist_id = ?1
+         ORDER BY pt.id",
+        JOINED_MUSIC_TRACK_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![playlist_id], music_track_from_row)?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn delete_playlist_owned(id: i64, owner_id: i64) -> Result<bool, Error> {
+    let mut conn = Connection::open(db_path())?;
+    let tx = conn.transaction()?;
+    let deleted = tx.execute("DELETE FROM playlists WHERE id = ?1 AND owner_id = ?2", params![id, owner_id])?;
+    if deleted > 0 {
+        tx.execute("DELETE FROM playlist_tracks WHERE playlist_id = ?1", params![id])?;
+    }
+    tx.commit()?;
+    Ok(deleted > 0)
+}
+
+pub fn add_quiz_point(guild_id: i64, user_id: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    let updated_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO music_quiz_scores (guild_id, user_id, points, updated_at) VALUES (?1, ?2, 1, ?3)
+         ON CONFLICT(guild_id, user_id) DO UPDATE SET points = points + 1, updated_at = excluded.updated_at",
+        params![guild_id, user_id, updated_at],
+    )?;
+    Ok(())
+}
+
+pub fn get_quiz_leaderboard(guild_id: i64, limit: i64) -> Result<Vec<(i64, i64)>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(
+        "SELECT user_id, points FROM music_quiz_scores
+         WHERE guild_id = ?1
+         ORDER BY points DESC, updated_at ASC
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![guild_id, limit], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn set_music_rating(guild_id: i64, user_id: i64, track_id: i64, rating: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    let updated_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO music_ratings (guild_id, user_id, track_id, rating, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(guild_id, user_id, track_id) DO UPDATE SET rating = excluded.rating, updated_at = excluded.updated_at",
+        params![guild_id, user_id, track_id, rating, updated_at],
+    )?;
+    Ok(())
+}
+
+pub fn toggle_music_favorite(guild_id: i64, user_id: i64, track_id: i64) -> Result<bool, Error> {
+    let conn = Connection::open(db_path())?;
+    let updated_at = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO music_ratings (guild_id, user_id, track_id, favorite, updated_at) VALUES (?1, ?2, ?3, 1, ?4)
+         ON CONFLICT(guild_id, user_id, track_id) DO UPDATE SET favorite = 1 - favorite, updated_at = excluded.updated_at",
+        params![guild_id, user_id, track_id, updated_at],
+    )?;
+    let favorite: i64 = conn.query_row(
+        "SELECT favorite FROM music_ratings WHERE guild_id = ?1 AND user_id = ?2 AND track_id = ?3",
+        params![guild_id, user_id, track_id],
+        |row| row.get(0),
+    )?;
+    Ok(favorite != 0)
+}
+
+pub fn get_music_favorites(guild_id: i64, user_id: i64) -> Result<Vec<MusicTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM music_ratings r
+         JOIN music_tracks t ON t.id = r.track_id
+         WHERE r.guild_id = ?1 AND r.user_id = ?2 AND r.favorite = 1
+         ORDER BY r.updated_at DESC",
+        JOINED_MUSIC_TRACK_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![guild_id, user_id], music_track_from_row)?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+#[derive(Debug, Clone)]
+pub struct RatedTrackRow {
+    pub track: MusicTrackRow,
+    pub average: f64,
+    pub ratings: i64,
+    pub favorites: i64,
+}
+
+/// Returns the guild's highest-rated tracks; ties are broken by number of ratings and favourites.
+pub fn get_top_rated_tracks(guild_id: i64, limit: i64) -> Result<Vec<RatedTrackRow>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, AVG(r.rating), COUNT(r.rating), SUM(r.favorite) FROM music_ratings r
+         JOIN music_tracks t ON t.id = r.track_id
+         WHERE r.guild_id = ?1
+         GROUP BY t.id
+         HAVING COUNT(r.rating) > 0
+         ORDER BY AVG(r.rating) DESC, COUNT(r.rating) DESC, SUM(r.favorite) DESC
+         LIMIT ?2",
+        JOINED_MUSIC_TRACK_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![guild_id, limit], |row| {
+        Ok(RatedTrackRow {
+            track: music_track_from_row(row)?,
+            average: row.get(12)?,
+            ratings: row.get(13)?,
+            favorites: row.get(14)?,
+        })
+    })?;
+    let mut out = Vec::new();
+    for r in rows { out.push(r?); }
+    Ok(out)
+}
+
+pub fn get_music_track_ratings(guild_id: i64) -> Result<HashMap<i64, f64>, Error> {
+    let conn = Connection::open(db_path())?;
+    let mut stmt = conn.prepare(
+        "SELECT track_id, AVG(rating) FROM music_ratings
+         WHERE guild_id = ?1 AND rating IS NOT NULL
+         GROUP BY track_id",
+    )?;
+    let rows = stmt.query_map(params![guild_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)))?;
+    let mut out = HashMap::new();
+    for r in rows {
+        let (track_id, average) = r?;
+        out.insert(track_id, average);
+    }
+    Ok(out)
+}
+
+pub fn get_voice_volume(guild_id: i64) -> Result<i64, Error> {
+    let conn = Connection::open(db_path())?;
+    let volume = conn
+        .query_row("SELECT volume FROM voice_settings WHERE guild_id = ?1", params![guild_id], |row| row.get(0))
+        .optional()?;
+    Ok(volume.unwrap_or(100))
+}
+
+pub fn set_voice_volume(guild_id: i64, volume: i64) -> Result<(), Error> {
+    let conn = Connection::open(db_path())?;
+    conn.execute(
+        "INSERT INTO voice_settings (guild_id, volume) VALUES (?1, ?2)
+         ON CONFLICT(guild_id) DO UPDATE SET volume = excluded.volume",
+        params![guild_id, volume],
+    )?;
+    Ok(())
+}
@@ -3,8 +3,14 @@ use tokio_cron_scheduler::{Job, JobScheduler};
 use tracing::{error, info, warn};
 
 use crate::shared::types::Error;
-use crate::recipe::utils::{get_random_meal, format_meal};
+use crate::recipe::cache::random_unsent_cached_meal;
+use crate::recipe::models::{ExclusionScope, RecipeExclusions};
+use crate::recipe::utils::{get_random_meal, format_meal, load_exclusions, recipe_components, RecipeView};
 use crate::shared::db::{was_recipe_sent, log_recipe_sent};
+use crate::music::library::track_path;
+use crate::music::models::StatsPeriod;
+use crate::music::utils::{song_components, stats_embed, track_embed, upload_limit, with_cover_art_within};
+use crate::shared::db::{get_music_stats, log_daily_song, log_music_play, random_music_track, random_new_daily_song};
 
 pub async fn setup_daily_recipe_scheduler(
     ctx: &serenity::Context,
@@ -14,23 +20,40 @@ pub async fn setup_daily_recipe_scheduler(
     info!("Setting up daily recipe scheduler...");
 
     let http_client = ctx.http.clone();
-    let reqwest_client = reqwest::Client::new();
     let scheduler = JobScheduler::new().await?;
 
     let job = Job::new_async(schedule, move |_uuid, _lock| {
         let http = http_client.clone();
-        let reqwest = reqwest_client.clone();
         let channel = channel;
 
         Box::pin(async move {
             info!("Running scheduled job: Sending daily recipe...");
 
+            let exclusions = match channel.to_channel(&http).await {
+                Ok(serenity::Channel::Guild(gchan)) => load_exclusions(ExclusionScope::Guild, gchan.guild_id.get()),
+                Ok(_) => RecipeExclusions::default(),
+                Err(e) => {
+                    warn!("Failed to look up daily recipe channel {}: {}", channel, e);
+                    RecipeExclusions::default()
+                }
+            };
+            // Recipes the server's exclusions rule out are rerolled without counting as a try.
+            let mut rerolls = 0u8;
+
             let mut tries = 0u8;
             let mut chosen: Option<(crate::recipe::models::Meal, String)> = None;
             let mut is_repeat = false;
 
             while tries < 5 {
-                match get_random_meal(&reqwest).await {
+                match get_random_meal().await {
+                    Ok(Some(meal)) if !exclusions.allows(&meal) => {
+                        rerolls += 1;
+                        if rerolls >= 10 {
+                            warn!("Scheduled job: No recipe fitting the server's exclusions after {} rerolls.", rerolls);
+                            break;
+                        }
+                        continue;
+                    }
                     Ok(Some(meal)) => {
                         let recipe_id = meal
                             .id
@@ -71,9 +94,25 @@ pub async fn setup_daily_recipe_scheduler(
                 }
             }
 
+            // The API only offered recipes we've posted before (or couldn't be reached): try the cache.
+            if chosen.is_none() || is_repeat {
+                match random_unsent_cached_meal(&exclusions) {
+                    Ok(Some(meal)) => {
+                        let recipe_id = meal.id.clone().unwrap_or_else(|| meal.name.clone());
+                        chosen = Some((meal, recipe_id));
+                        is_repeat = false;
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Scheduled job: Failed to read cached recipes: {}", e),
+                }
+            }
+
             if let Some((meal, recipe_id)) = chosen {
                 let embed = format_meal(&meal, true, is_repeat);
-                let builder = serenity::CreateMessage::new().embed(embed);
+                let view = RecipeView { daily: true, repeat: is_repeat, ..RecipeView::default() };
+                let builder = serenity::CreateMessage::new()
+                    .embed(embed)
+                    .components(recipe_components(&meal, 0, &view));
                 if let Err(e) = channel.send_message(&http, builder).await {
                     error!("Failed to send daily recipe: {}", e);
                 } else {
@@ -99,6 +138,186 @@ pub async fn setup_daily_recipe_scheduler(
     Ok(())
 }
 
+pub async fn setup_daily_song_scheduler(
+    ctx: &serenity::Context,
+    schedule: &str,
+    channel: serenity::ChannelId,
+) -> Result<(), Error> {
+    info!("Setting up daily song scheduler...");
+
+    let http_client = ctx.http.clone();
+    let scheduler = JobScheduler::new().await?;
+
+    let job = Job::new_async(schedule, move |_uuid, _lock| {
+        let http = http_client.clone();
+        let channel = channel;
+
+        Box::pin(async move {
+            info!("Running scheduled job: Sending song of the day...");
+
+            let guild_id = match channel.to_channel(&http).await {
+                Ok(serenity::Channel::Guild(gchan)) => gchan.guild_id,
+                Ok(_) => {
+                    error!("Daily song channel {} is not a server channel", channel);
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to look up daily song channel {}: {}", channel, e);
+                    return;
+                }
+            };
+
+            // Every song has been featured once the new picks run out; start repeating.
+            let mut is_repeat = false;
+            let chosen = match random_new_daily_song(guild_id.get() as i64) {
+                Ok(Some(track)) => Some(track),
+                Ok(None) => {
+                    is_repeat = true;
+                    random_music_track("", None).unwrap_or_else(|e| {
+                        error!("Scheduled job: Failed to pick a song: {}", e);
+                        None
+                    })
+                }
+                Err(e) => {
+                    error!("Scheduled job: Failed to pick a song: {}", e);
+                    None
+                }
+            };
+            let Some(track) = chosen else {
+                warn!("Scheduled job: No songs in the music library.");
+                return;
+            };
+
+            let song_path = track_path(&track);
+            let mut embed = track_embed(&track, "🎵 Song of the Day");
+            if !track.series.is_empty() {
+                embed = embed.field("Series", &track.series, true);
+            }
+            if is_repeat {
+                embed = embed.field("Note", "Every song has been the song of the day already, so here's an encore.", false);
+            }
+
+            // Attach the song itself when it fits; the play button works either way.
+            let tier = guild_id.to_partial_guild(&http).await.ok().map(|g| g.premium_tier);
+            let limit = upload_limit(tier);
+            let mut song_file = None;
+            if (track.size as u64) <= limit {
+                match tokio::fs::read(&song_path).await {
+                    Ok(content) => {
+                        let file_name = song_path
+                            .file_name()
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_else(|| "song_of_the_day.mp3".to_string());
+                        song_file = Some(serenity::CreateAttachment::bytes(content, file_name));
+                    }
+                    Err(e) => warn!("Failed to read song of the day {}: {}", song_path.display(), e),
+                }
+            }
+
+            let budget = limit.saturating_sub(song_file.as_ref().map_or(0, |file| file.data.len() as u64));
+            let (embed, cover) = with_cover_art_within(embed, &song_path, budget).await;
+            let mut builder = serenity::CreateMessage::new()
+                .embed(embed)
+                .components(song_components(track.id, true));
+            if let Some(cover) = cover {
+                builder = builder.add_file(cover);
+            }
+            if let Some(song_file) = song_file {
+                builder = builder.add_file(song_file);
+            }
+
+            if let Err(e) = channel.send_message(&http, builder).await {
+                error!("Failed to send song of the day: {}", e);
+            } else {
+                if let Err(e) = log_daily_song(guild_id.get() as i64, track.id, &track.title) {
+                    warn!("Failed to log song of the day: {}", e);
+                }
+                if let Err(e) = log_music_play(guild_id.get() as i64, track.id, None, "song of the day") {
+                    warn!("Failed to log music play: {}", e);
+                }
+                info!("Successfully sent song of the day to channel {}", channel);
+            }
+        })
+    })?;
+
+    scheduler.add(job).await?;
+    info!("Daily song job added with schedule: {}", schedule);
+
+    tokio::spawn(async move {
+        if let Err(e) = scheduler.start().await {
+            error!("Daily song scheduler failed to start: {}", e);
+        }
+    });
+    info!("Daily song scheduler started.");
+
+    Ok(())
+}
+
+pub async fn setup_weekly_music_stats_scheduler(
+    ctx: &serenity::Context,
+    schedule: &str,
+    channel: serenity::ChannelId,
+) -> Result<(), Error> {
+    info!("Setting up weekly music stats scheduler...");
+
+    let http_client = ctx.http.clone();
+    let scheduler = JobScheduler::new().await?;
+
+    let job = Job::new_async(schedule, move |_uuid, _lock| {
+        let http = http_client.clone();
+        let channel = channel;
+
+        Box::pin(async move {
+            info!("Running scheduled job: Sending weekly music stats...");
+
+            let guild_id = match channel.to_channel(&http).await {
+                Ok(serenity::Channel::Guild(gchan)) => gchan.guild_id,
+                Ok(_) => {
+                    error!("Music stats channel {} is not a server channel", channel);
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to look up music stats channel {}: {}", channel, e);
+                    return;
+                }
+            };
+
+            let since = StatsPeriod::Week.since();
+            let stats = match get_music_stats(guild_id.get() as i64, since.as_deref(), None, 5) {
+                Ok(stats) => stats,
+                Err(e) => {
+                    error!("Scheduled job: Failed to gather music stats: {}", e);
+                    return;
+                }
+            };
+            // Nothing to report after a quiet week.
+            if stats.plays == 0 {
+                info!("Scheduled job: No music played this week, skipping the summary.");
+                return;
+            }
+
+            let embed = stats_embed(&stats, "📊 This Week in Music");
+            if let Err(e) = channel.send_message(&http, serenity::CreateMessage::new().embed(embed)).await {
+                error!("Failed to send weekly music stats: {}", e);
+            } else {
+                info!("Successfully sent weekly music stats to channel {}", channel);
+            }
+        })
+    })?;
+
+    scheduler.add(job).await?;
+    info!("Weekly music stats job added with schedule: {}", schedule);
+
+    tokio::spawn(async move {
+        if let Err(e) = scheduler.start().await {
+            error!("Weekly music stats scheduler failed to start: {}", e);
+        }
+    });
+    info!("Weekly music stats scheduler started.");
+
+    Ok(())
+}
+
 pub async fn setup_reminder_scheduler(
     ctx: &serenity::Context,
     channel: serenity::ChannelId,
@@ -2,5 +2,8 @@ pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 
-#[derive(Debug)]
-pub struct Data {}
+#[derive(Debug, Default)]
+pub struct Data {
+    pub quiz_channels: std::sync::Mutex<std::collections::HashMap<poise::serenity_prelude::ChannelId, std::sync::Arc<crate::music::quiz::QuizRun>>>,
+    pub radio_sessions: tokio::sync::Mutex<std::collections::HashMap<poise::serenity_prelude::GuildId, tokio::task::AbortHandle>>,
+}
@@ -1,6 +1,6 @@
 use poise::serenity_prelude::UserId;
 
-pub use crate::recipe::utils::{fetch_from_mealdb, format_meal, get_and_format_random_recipe, mealdb_base_url};
+pub use crate::recipe::utils::{format_meal, get_and_format_random_recipe, mealdb_base_url};
 
 pub fn special_user_id(user: &str) -> Option<UserId> {
     match std::env::var(user) {
@@ -1,6 +1,8 @@
+use crate::music::models::QueuedSong;
+use crate::music::utils::{is_dj, track_volume};
 use crate::shared::types::{Context, Error};
-use crate::shared::db::{tts_is_signed, tts_signup, tts_signout};
-use crate::voice::utils::{find_user_voice_channel};
+use crate::shared::db::{get_music_settings, get_voice_volume, set_voice_volume, tts_is_signed, tts_signup, tts_signout};
+use crate::voice::utils::{find_user_voice_channel, guild_call, join_voice_channel};
 
 /// Text-to-Speech features
 #[poise::command(slash_command, subcommands("signup", "signout", "join", "leave"))]
@@ -57,10 +59,7 @@ pub async fn join(ctx: Context<'_>) -> Result<(), Error> {
         return Ok(());
     };
 
-    let manager = songbird::get(ctx.serenity_context()).await
-        .expect("Songbird Voice client placed in at initialisation.");
-
-    match manager.join(guild_id, channel_id).await {
+    match join_voice_channel(ctx.serenity_context(), guild_id, channel_id).await {
         Ok(_call_lock) => {
             ctx.say(format!("Joined <#{}>.", channel_id.get())).await?;
         }
@@ -82,10 +81,11 @@ pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
 
     let manager = songbird::get(ctx.serenity_context()).await
         .expect("Songbird Voice client placed in at initialisation.");
-    if manager.get(guild_id).is_none() {
+    let Some(call_lock) = manager.get(guild_id) else {
         ctx.say("I'm not in a voice channel.").await?;
         return Ok(());
-    }
+    };
+    call_lock.lock().await.queue().stop();
 
     if let Err(e) = manager.leave(guild_id).await {
         tracing::error!("Songbird leave error: {}", e);
@@ -96,3 +96,56 @@ pub async fn leave(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Voice playback settings
+#[poise::command(slash_command, subcommands("volume"))]
+pub async fn voice(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.say("Use `/voice volume` to show or change the playback volume.").await?;
+    Ok(())
+}
+
+/// Show or change the volume of music and TTS in this server
+#[poise::command(slash_command, guild_only)]
+pub async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume in percent (default 100)"]
+    #[min = 0]
+    #[max = 200]
+    level: Option<u8>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() { Some(g) => g, None => {
+        ctx.say("This command can only be used in a server.").await?; return Ok(());
+    }};
+
+    let Some(level) = level else {
+        let current = get_voice_volume(guild_id.get() as i64)?;
+        ctx.say(format!("🔊 Volume is set to {}%.", current)).await?;
+        return Ok(());
+    };
+
+    let can_manage = ctx
+        .author_member()
+        .await
+        .is_some_and(|member| member.permissions.is_some_and(|p| p.manage_guild()));
+    if !can_manage && !is_dj(ctx, get_music_settings(guild_id.get() as i64)?.dj_role_id).await {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("Only DJs and members who can manage the server can change the volume.")
+                .ephemeral(true),
+        ).await?;
+        return Ok(());
+    }
+
+    set_voice_volume(guild_id.get() as i64, level as i64)?;
+
+    // Apply the new level to whatever is playing or queued right now.
+    if let Some(call_lock) = guild_call(ctx.serenity_context(), guild_id).await {
+        let factor = level as f32 / 100.0;
+        for track in call_lock.lock().await.queue().current_queue() {
+            let _ = track.set_volume(track_volume(&track.data::<QueuedSong>().track, factor));
+        }
+    }
+
+    ctx.say(format!("🔊 Volume set to {}%.", level)).await?;
+    Ok(())
+}
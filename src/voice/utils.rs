@@ -1,8 +1,18 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use poise::serenity_prelude as serenity;
+use songbird::events::{Event, EventContext, EventHandler, TrackEvent};
+use songbird::input;
+use songbird::tracks::TrackHandle;
+use songbird::Call;
 use tokio::fs;
+use tokio::sync::Mutex;
 use tokio::task;
+use crate::music::models::QueuedSong;
+use crate::music::utils::track_volume;
+use crate::shared::db::get_voice_volume;
 use crate::shared::types::Error;
 
 pub async fn find_user_voice_channel(ctx: &serenity::Context, guild_id: serenity::GuildId, user_id: serenity::UserId) -> Option<serenity::ChannelId> {
@@ -12,6 +22,137 @@ pub async fn find_user_voice_channel(ctx: &serenity::Context, guild_id: serenity
     state.channel_id
 }
 
+pub fn voice_channel_listeners(ctx: &serenity::Context, guild_id: serenity::GuildId, channel_id: serenity::ChannelId) -> Vec<serenity::UserId> {
+    let Some(guild) = guild_id.to_guild_cached(ctx) else { return Vec::new() };
+    guild
+        .voice_states
+        .values()
+        .filter(|state| state.channel_id == Some(channel_id))
+        .filter(|state| {
+            let is_bot = state
+                .member
+                .as_ref()
+                .map(|member| member.user.bot)
+                .or_else(|| guild.members.get(&state.user_id).map(|member| member.user.bot))
+                .unwrap_or(false);
+            !is_bot
+        })
+        .map(|state| state.user_id)
+        .collect()
+}
+
+pub async fn join_voice_channel(ctx: &serenity::Context, guild_id: serenity::GuildId, channel_id: serenity::ChannelId) -> Result<Arc<Mutex<Call>>, Error> {
+    let manager = songbird::get(ctx).await
+        .expect("Songbird Voice client placed in at initialisation.");
+
+    if let Some(call_lock) = manager.get(guild_id) {
+        let current = call_lock.lock().await.current_channel();
+        if current == Some(channel_id.into()) {
+            return Ok(call_lock);
+        }
+    }
+
+    Ok(manager.join(guild_id, channel_id).await?)
+}
+
+pub async fn guild_call(ctx: &serenity::Context, guild_id: serenity::GuildId) -> Option<Arc<Mutex<Call>>> {
+    let manager = songbird::get(ctx).await
+        .expect("Songbird Voice client placed in at initialisation.");
+    manager.get(guild_id)
+}
+
+pub fn guild_volume(guild_id: serenity::GuildId) -> f32 {
+    match get_voice_volume(guild_id.get() as i64) {
+        Ok(volume) => volume as f32 / 100.0,
+        Err(e) => {
+            tracing::warn!("Failed to read voice volume: {}", e);
+            1.0
+        }
+    }
+}
+
+/// Share of its volume music is lowered to while a TTS message is being read out.
+const TTS_DUCK_FACTOR: f32 = 0.2;
+
+/// Clips being read out per guild, and the song they ducked. Clips can overlap, so the song is
+/// only turned back up when the last one ends.
+#[derive(Default)]
+struct Ducking {
+    clips: Vec<TrackHandle>,
+    music: Option<(TrackHandle, f32)>,
+}
+
+static DUCKING: LazyLock<std::sync::Mutex<HashMap<serenity::GuildId, Ducking>>> = LazyLock::new(Default::default);
+
+/// Plays a TTS clip at the guild's volume on top of the music queue, ducking the current song
+/// until the clip ends. The clip file is removed once playback finishes.
+pub fn play_tts(call: &mut Call, guild_id: serenity::GuildId, path: PathBuf, volume: f32) {
+    let music = call.queue().current();
+    let track = call.play_input(input::File::new(path.clone()).into());
+    let _ = track.set_volume(volume);
+
+    {
+        let mut guilds = DUCKING.lock().unwrap_or_else(|e| e.into_inner());
+        let ducking = guilds.entry(guild_id).or_default();
+        ducking.clips.push(track.clone());
+        if let Some(music) = music {
+            let music_volume = track_volume(&music.data::<QueuedSong>().track, volume);
+            let _ = music.set_volume(music_volume * TTS_DUCK_FACTOR);
+            ducking.music = Some((music, music_volume));
+        }
+    }
+
+    let finished = TtsFinished { guild_id, clip: track.clone(), path };
+    let _ = track.add_event(Event::Track(TrackEvent::End), finished.clone());
+    let _ = track.add_event(Event::Track(TrackEvent::Error), finished);
+}
+
+#[derive(Clone)]
+struct TtsFinished {
+    guild_id: serenity::GuildId,
+    clip: TrackHandle,
+    path: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl EventHandler for TtsFinished {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let others = {
+            let mut guilds = DUCKING.lock().unwrap_or_else(|e| e.into_inner());
+            match guilds.get_mut(&self.guild_id) {
+                Some(ducking) => {
+                    ducking.clips.retain(|clip| clip.uuid() != self.clip.uuid());
+                    ducking.clips.clone()
+                }
+                None => Vec::new(),
+            }
+        };
+
+        // Clips dropped with the call never report their end, so only count ones still playing.
+        let mut playing = Vec::new();
+        for clip in others {
+            if clip.get_info().await.is_ok_and(|state| !state.playing.is_done()) {
+                playing.push(clip.uuid());
+            }
+        }
+
+        {
+            let mut guilds = DUCKING.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(ducking) = guilds.get_mut(&self.guild_id) {
+                ducking.clips.retain(|clip| playing.contains(&clip.uuid()));
+                if ducking.clips.is_empty() {
+                    if let Some((music, volume)) = ducking.music.take() {
+                        let _ = music.set_volume(volume);
+                    }
+                    guilds.remove(&self.guild_id);
+                }
+            }
+        }
+        cleanup_file(self.path.clone()).await;
+        None
+    }
+}
+
 pub async fn synthesize_to_wav(text: &str) -> Result<PathBuf, Error> {
     let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
     let tmp_path = std::env::temp_dir().join(format!("shaggy_tts_{}.wav", now));